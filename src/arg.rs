@@ -1,34 +1,351 @@
-use clap::{Arg, ArgGroup, Command};
+use anyhow::{bail, Result};
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use dir_cleaner_rust::features::cleaner_last_modified_time::TimeField;
+use dir_cleaner_rust::features::compress::Compression;
+use dir_cleaner_rust::features::filter::{arg_verbs, verbs};
+use dir_cleaner_rust::features::registry;
+use dir_cleaner_rust::features::utils::Shred;
+use dir_cleaner_rust::report::ReportFormat;
 
 const APP: &str = "Directory cleaner";
 
+/// Subcommands working on a quarantine, journal or plan, or analyzing a directory, rather than
+/// cleaning it. The plan subcommand takes the options of a cleaning run and is parsed into
+/// [`Args::plan`].
 #[derive(Debug)]
+pub enum Subcommand {
+    Restore {
+        quarantine: String,
+        run: Option<String>,
+        paths: Vec<String>,
+        dry_run: bool,
+    },
+    Purge {
+        quarantine: String,
+        older_than: Option<String>,
+        dry_run: bool,
+    },
+    Undo {
+        run_id: String,
+        journal_dir: Option<String>,
+        dry_run: bool,
+    },
+    Apply {
+        plan: String,
+        journal_dir: Option<String>,
+        dry_run: bool,
+    },
+    Analyze {
+        dir: String,
+        /// Values given to each registered filter, each one a candidate
+        filters: HashMap<&'static str, Vec<String>>,
+        files_to_ignore: Vec<String>,
+        time_field: TimeField,
+        top: usize,
+    },
+}
+
+#[derive(Debug, Default)]
 pub struct Args {
-    pub types: Vec<String>,
-    pub min_size: Option<u64>,
     pub dir: String,
     pub dry_run: bool,
-    pub remove_duplicates: bool,
-    pub age: Option<String>,
+    pub interactive: bool,
+    pub tui: bool,
+    pub trash: bool,
+    pub quarantine: Option<String>,
+    pub archive: Option<String>,
+    pub compress: Option<Compression>,
+    pub shred: Option<Shred>,
+    pub journal_dir: Option<String>,
+    /// Write the selected files to this plan instead of acting on them
+    pub plan: Option<String>,
+    pub report_format: ReportFormat,
+    /// Write the report to this file instead of stdout
+    pub report_file: Option<String>,
+    pub time_field: TimeField,
     pub files_to_ignore: Vec<String>,
+    /// Values given to each registered filter, by filter name
+    pub filters: HashMap<&'static str, Vec<String>>,
+    /// Values given to the options of the registered filters, by argument id
+    pub options: HashMap<String, Vec<String>>,
     pub config_file: Option<String>,
+    pub command: Option<Subcommand>,
 }
 
 impl Args {
     pub fn clear(&mut self) {
-        self.min_size = None;
-        self.types = vec![];
         self.dir = "".to_string();
-        self.age = None;
+        self.time_field = TimeField::default();
         self.dry_run = false;
+        self.interactive = false;
+        self.tui = false;
+        self.trash = false;
+        self.quarantine = None;
+        self.archive = None;
+        self.compress = None;
+        self.shred = None;
+        self.journal_dir = None;
+        self.report_format = ReportFormat::default();
+        self.report_file = None;
         self.files_to_ignore = vec![];
-        self.remove_duplicates = false;
+        self.filters.clear();
+        self.options.clear();
     }
 }
 
 pub fn parse_args() -> Args {
-    let arg = Command::new(APP)
+    let arg = cleaning_command(Command::new(APP))
         .version("1.0")
+        .arg(dry_run_arg())
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            cleaning_command(Command::new("plan"))
+                .about("Select the files as a dry run would and write them to a plan file, to review it and run it with the apply subcommand")
+                .arg(
+                    Arg::new("plan")
+                        .value_name("PLAN")
+                        .required(true)
+                        .help("Plan file to write"),
+                ),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Put quarantined files back where they came from")
+                .arg(quarantine_root_arg())
+                .arg(
+                    Arg::new("run")
+                        .long("run")
+                        .value_name("RUN")
+                        .help("Only restore the files quarantined by this run"),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .num_args(0..)
+                        .value_name("PATTERN")
+                        .help("Only restore files whose original path matches one of these patterns, all files by default"),
+                )
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            Command::new("purge")
+                .about("Permanently delete quarantined files")
+                .arg(quarantine_root_arg())
+                .arg(
+                    Arg::new("older_than")
+                        .long("older-than")
+                        .value_name("AGE")
+                        .help("Only purge files quarantined before this age, e.g. 30d, or date (YYYY-MM-DD)"),
+                )
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("Reverse what a run did, as far as its action allows")
+                .arg(
+                    Arg::new("run_id")
+                        .value_name("RUN_ID")
+                        .required(true)
+                        .help("Id of the run, as printed at the end of the run"),
+                )
+                .arg(journal_dir_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            Command::new("apply")
+                .about("Act on the files of a plan written by the plan subcommand, leaving alone those that changed since")
+                .arg(
+                    Arg::new("plan")
+                        .value_name("PLAN")
+                        .required(true)
+                        .help("Plan file"),
+                )
+                .arg(journal_dir_arg())
+                .arg(dry_run_arg()),
+        )
+        .subcommand(
+            Command::new("analyze")
+                .about("Show where the space of a directory goes and what candidate filters would free, without touching anything")
+                .arg(
+                    Arg::new("directory")
+                        .short('d')
+                        .long("dir")
+                        .value_name("DIRECTORY")
+                        .required(true)
+                        .help("Directory to analyze"),
+                )
+                .args(registry().iter().map(|filter| {
+                    filter
+                        .arg()
+                        .help_heading("Candidate filters, the largest extensions and a few ages when none is given")
+                }))
+                .arg(ignore_paths_arg())
+                .arg(time_field_arg())
+                .arg(
+                    Arg::new("top")
+                        .long("top")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10")
+                        .help("How many entries each list shows"),
+                ),
+        )
+        .get_matches();
+
+    if let Some(("plan", sub)) = arg.subcommand() {
+        return Args {
+            plan: sub.get_one::<String>("plan").cloned(),
+            ..cleaning_args(sub)
+        };
+    }
+
+    if let Some((name, sub)) = arg.subcommand() {
+        let quarantine = || {
+            sub.get_one::<String>("quarantine")
+                .cloned()
+                .unwrap_or_default()
+        };
+        let dry_run = matches!(sub.try_get_one::<bool>("dry_run"), Ok(Some(true)));
+        let command = match name {
+            "restore" => Subcommand::Restore {
+                quarantine: quarantine(),
+                run: sub.get_one::<String>("run").cloned(),
+                paths: sub
+                    .get_many::<String>("paths")
+                    .map(|paths| paths.cloned().collect())
+                    .unwrap_or_default(),
+                dry_run,
+            },
+            "purge" => Subcommand::Purge {
+                quarantine: quarantine(),
+                older_than: sub.get_one::<String>("older_than").cloned(),
+                dry_run,
+            },
+            "undo" => Subcommand::Undo {
+                run_id: sub.get_one::<String>("run_id").cloned().unwrap_or_default(),
+                journal_dir: sub.get_one::<String>("journal_dir").cloned(),
+                dry_run,
+            },
+            "analyze" => Subcommand::Analyze {
+                dir: sub
+                    .get_one::<String>("directory")
+                    .cloned()
+                    .unwrap_or_default(),
+                filters: filter_values(sub),
+                files_to_ignore: sub
+                    .get_many::<String>("ignore_paths")
+                    .map(|paths| paths.cloned().collect())
+                    .unwrap_or_default(),
+                time_field: sub
+                    .get_one::<TimeField>("time_field")
+                    .copied()
+                    .unwrap_or_default(),
+                top: sub.get_one::<usize>("top").copied().unwrap_or(10),
+            },
+            _ => Subcommand::Apply {
+                plan: sub.get_one::<String>("plan").cloned().unwrap_or_default(),
+                journal_dir: sub.get_one::<String>("journal_dir").cloned(),
+                dry_run,
+            },
+        };
+        return Args {
+            command: Some(command),
+            ..Default::default()
+        };
+    }
+
+    cleaning_args(&arg)
+}
+
+/// The options of a cleaning run, from the command or the plan subcommand
+fn cleaning_args(arg: &ArgMatches) -> Args {
+    let dir = match arg.try_get_one::<String>("directory") {
+        Ok(Some(dir)) => dir.to_string(),
+        Ok(None) => {
+            eprintln!("No directory provided, expecting config file");
+            String::new()
+        }
+        Err(e) => {
+            eprintln!("Error processing directory: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // the plan subcommand has no --dry-run, the lookup then fails and leaves it off
+    let dry_run = matches!(arg.try_get_one::<bool>("dry_run"), Ok(Some(true)));
+
+    let interactive: bool = arg.get_one::<bool>("interactive").copied().unwrap_or(false);
+
+    let tui: bool = arg.get_one::<bool>("tui").copied().unwrap_or(false);
+
+    let trash: bool = arg.get_one::<bool>("trash").copied().unwrap_or(false);
+
+    let quarantine = arg.get_one::<String>("quarantine").cloned();
+
+    let archive = arg.get_one::<String>("archive").cloned();
+
+    let compress = arg.get_one::<Compression>("compress").copied();
+
+    let shred = arg.get_one::<Shred>("shred").cloned();
+
+    let journal_dir = arg.get_one::<String>("journal_dir").cloned();
+
+    let report_file = arg.get_one::<String>("report_file").cloned();
+
+    let report_format = match (arg.get_one::<ReportFormat>("report_format"), &report_file) {
+        (Some(format), _) => *format,
+        (None, Some(_)) => ReportFormat::Json,
+        (None, None) => ReportFormat::default(),
+    };
+
+    let time_field: TimeField = arg
+        .get_one::<TimeField>("time_field")
+        .copied()
+        .unwrap_or_default();
+
+    let files_to_ignore = match arg.get_many::<String>("ignore_paths") {
+        Some(types) => types.cloned().collect(),
+        None => Vec::new(),
+    };
+
+    let filters = filter_values(arg);
+
+    let options = option_values(arg);
+
+    let config_file: Option<String> = match arg.try_get_one::<String>("config_file") {
+        Ok(Some(val)) => Some(val.to_string()),
+        Ok(None) => None,
+        Err(_) => None,
+    };
+
+    Args {
+        dir,
+        dry_run,
+        interactive,
+        tui,
+        trash,
+        quarantine,
+        archive,
+        compress,
+        shred,
+        journal_dir,
+        plan: None,
+        report_format,
+        report_file,
+        time_field,
+        files_to_ignore,
+        filters,
+        options,
+        config_file,
+        command: None,
+    }
+}
+
+/// The arguments of a cleaning run, shared by the command itself and the plan subcommand
+fn cleaning_command(command: Command) -> Command {
+    command
         .arg(
             Arg::new("directory")
                 .short('d')
@@ -37,53 +354,79 @@ pub fn parse_args() -> Args {
                 .help("Directory to clean up"),
         )
         .arg(
-            Arg::new("types")
-                .short('t')
-                .long("types")
+            Arg::new("interactive")
+                .long("interactive")
                 .required(false)
-                .num_args(1..) // Allow multiple values
-                .help("Types to clean (space-separated)"),
+                .value_parser(clap::value_parser!(bool))
+                .help("Ask which of the selected files to act on: all, none, file by file or directory by directory"),
         )
         .arg(
-            Arg::new("size")
-                .short('s')
-                .long("size")
+            Arg::new("tui")
+                .long("tui")
                 .required(false)
-                .value_parser(clap::value_parser!(u64))
-                .help("Minimum size to clear (in bytes)"),
+                .value_parser(clap::value_parser!(bool))
+                .conflicts_with("interactive")
+                .help("Browse the selected files full screen as a tree with directory sizes, and choose which to act on"),
         )
         .arg(
-            Arg::new("dry_run")
-                .short('n')
-                .long("dryrun")
+            Arg::new("trash")
+                .long("trash")
                 .required(false)
                 .value_parser(clap::value_parser!(bool))
-                .help("Dry run"),
+                .help("Move files to the trash instead of deleting them"),
         )
         .arg(
-            Arg::new("remove_duplicates")
-                .short('r')
-                .long("dedup")
+            Arg::new("quarantine")
+                .long("quarantine")
+                .value_name("DIR")
                 .required(false)
-                .value_parser(clap::value_parser!(bool))
-                .help("Remove Duplicate"),
+                .conflicts_with("trash")
+                .help("Move files into this quarantine directory instead of deleting them, see the restore and purge subcommands"),
+        )
+        .arg(
+            Arg::new("archive")
+                .long("archive")
+                .value_name("FILE")
+                .required(false)
+                .conflicts_with_all(["trash", "quarantine"])
+                .help("Move files into a new .tar.gz, .tar.zst or .zip archive, deleting them once it's verified"),
+        )
+        .arg(
+            Arg::new("compress")
+                .long("compress")
+                .value_name("FORMAT")
+                .required(false)
+                .value_parser(|s: &str| s.parse::<Compression>().map_err(|e| e.to_string()))
+                .conflicts_with_all(["trash", "quarantine", "archive"])
+                .help("Replace files with a compressed copy instead of deleting them: gzip or zstd"),
+        )
+        .arg(
+            Arg::new("shred")
+                .long("shred")
+                .value_name("PASSES")
+                .required(false)
+                .value_parser(|s: &str| s.parse::<Shred>().map_err(|e| e.to_string()))
+                .conflicts_with_all(["trash", "quarantine", "archive", "compress"])
+                .help("Overwrite files before deleting them, with passes such as random,random,zeros or a number of random passes. Ineffective on copy-on-write filesystems and SSDs"),
         )
+        .arg(journal_dir_arg())
         .arg(
-            Arg::new("age")
-                .short('a')
-                .long("age")
+            Arg::new("report_format")
+                .long("report-format")
+                .value_name("FORMAT")
                 .required(false)
-                .value_parser(clap::value_parser!(String))
-                .help("Specify the cutoff date in YYYY-MM-DD format"),
+                .value_parser(|s: &str| s.parse::<ReportFormat>().map_err(|e| e.to_string()))
+                .help("Report as colored text (default), json for scripts, csv for spreadsheets, markdown for pull request comments or html for a standalone page with charts"),
         )
         .arg(
-            Arg::new("ignore_paths")
-                .short('i')
-                .long("files_to_ignore")
+            Arg::new("report_file")
+                .long("report-file")
+                .value_name("FILE")
                 .required(false)
-                .num_args(1..) // Allow multiple values
-                .help("Files to ignore (space-separated)"),
+                .help("Write the json, csv, markdown or html report to this file instead of stdout, json unless --report-format says otherwise"),
         )
+        .arg(time_field_arg())
+        .arg(ignore_paths_arg())
         .arg(
             Arg::new("config_file")
                 .short('f')
@@ -91,70 +434,374 @@ pub fn parse_args() -> Args {
                 .value_name("FILE")
                 .help("Config file containing cleanup metadata"),
         )
+        .args(registry().iter().map(|filter| filter.arg()))
+        .args(registry().iter().flat_map(|filter| filter.options()))
         .group(
             ArgGroup::new("directrory_or_config_file")
-                .args(&["directory", "config_file"])
+                .args(["directory", "config_file"])
                 .required(true), // One of these must be present
         )
-        .get_matches();
+}
 
-    let dir = match arg.try_get_one::<String>("directory") {
-        Ok(Some(dir)) => dir.to_string(),
-        Ok(None) => {
-            println!("No directory provided, expecting config file");
-            String::new()
+/// Values given to each registered filter, by filter name
+fn filter_values(arg: &ArgMatches) -> HashMap<&'static str, Vec<String>> {
+    let mut filters = HashMap::new();
+    for filter in registry() {
+        if let Some(values) = arg.get_raw(filter.name()) {
+            let values = values.map(|v| v.to_string_lossy().to_string()).collect();
+            filters.insert(filter.name(), values);
         }
-        Err(e) => {
-            eprintln!("Error processing directory: {:?}", e);
-            std::process::exit(1);
+    }
+    filters
+}
+
+/// Values given to the options of the registered filters, by argument id
+fn option_values(arg: &ArgMatches) -> HashMap<String, Vec<String>> {
+    let mut options = HashMap::new();
+    for option in registry().iter().flat_map(|filter| filter.options()) {
+        let id = option.get_id().as_str();
+        if let Ok(Some(values)) = arg.try_get_raw(id) {
+            let values = values.map(|v| v.to_string_lossy().to_string()).collect();
+            options.insert(id.to_string(), values);
         }
-    };
+    }
+    options
+}
 
-    let types = match arg.get_many::<String>("types") {
-        Some(types) => types.cloned().collect(),
-        None => Vec::new(),
-    };
+fn ignore_paths_arg() -> Arg {
+    Arg::new("ignore_paths")
+        .short('i')
+        .long("files_to_ignore")
+        .required(false)
+        .num_args(1..) // Allow multiple values
+        .help("Files or directories to protect (space-separated): globs such as *.log, /build or **/cache/, or re:<REGEX>")
+}
 
-    let min_size: Option<u64> = match arg.get_one::<u64>("size") {
-        Some(size) => Some(*size),
-        None => None,
-    };
+fn time_field_arg() -> Arg {
+    Arg::new("time_field")
+        .long("time-field")
+        .required(false)
+        .value_parser(|s: &str| s.parse::<TimeField>().map_err(|e| e.to_string()))
+        .help("Timestamp compared by --age and --newer: mtime (default), atime, ctime or birth")
+}
 
-    let dry_run: bool = match arg.get_one::<bool>("dry_run") {
-        Some(dr) => *dr,
-        None => false,
-    };
+fn quarantine_root_arg() -> Arg {
+    Arg::new("quarantine")
+        .short('q')
+        .long("quarantine")
+        .value_name("DIR")
+        .required(true)
+        .help("Quarantine directory")
+}
 
-    let remove_duplicates: bool = match arg.get_one::<bool>("remove_duplicates") {
-        Some(dr) => *dr,
-        None => false,
-    };
+fn journal_dir_arg() -> Arg {
+    Arg::new("journal_dir")
+        .long("journal-dir")
+        .value_name("DIR")
+        .required(false)
+        .help("Directory of the run journals, ~/.local/state/dir-cleaner-rust/journal by default")
+}
 
-    let age: Option<String> = match arg.try_get_one::<String>("age") {
-        Ok(Some(date)) => Some(date.to_string()),
-        Ok(None) => None,
-        Err(_) => None,
-    };
+fn dry_run_arg() -> Arg {
+    Arg::new("dry_run")
+        .short('n')
+        .long("dryrun")
+        .required(false)
+        .value_parser(clap::value_parser!(bool))
+        .help("Dry run")
+}
 
-    let files_to_ignore = match arg.get_many::<String>("ignore_paths") {
-        Some(types) => types.cloned().collect(),
-        None => Vec::new(),
-    };
+pub fn build_args(args: &mut Args, line_arg: &[&str]) -> Result<()> {
+    if line_arg.is_empty() {
+        return Ok(());
+    }
 
-    let config_file: Option<String> = match arg.try_get_one::<String>("config_file") {
-        Ok(Some(val)) => Some(val.to_string()),
-        Ok(None) => None,
-        Err(_) => None,
-    };
+    let verb = line_arg[0];
+    if let Some(filter) = registry()
+        .iter()
+        .find(|filter| verbs(**filter).iter().any(|v| v == verb))
+    {
+        if args.filters.contains_key(filter.name()) {
+            eprint!("Found duplicate verb, {}", verb);
+            return Ok(());
+        }
+        if line_arg.len() > 1 || filter.is_flag() {
+            let values = line_arg[1..].iter().map(|v| v.to_string()).collect();
+            args.filters.insert(filter.name(), values);
+        } else {
+            eprint!(
+                "Expected at least one arg for specified verb, {}, skipping...",
+                verb
+            );
+        }
+        return Ok(());
+    }
 
-    Args {
-        types,
-        min_size,
-        dir,
-        dry_run,
-        remove_duplicates,
-        age,
-        files_to_ignore,
-        config_file,
+    if let Some(option) = registry()
+        .iter()
+        .flat_map(|filter| filter.options())
+        .find(|option| arg_verbs(option).iter().any(|v| v == verb))
+    {
+        let id = option.get_id().to_string();
+        if args.options.contains_key(&id) {
+            eprint!("Found duplicate verb, {}", verb);
+            return Ok(());
+        }
+        // an option given without a value is a flag being set
+        let values = line_arg[1..].iter().map(|v| v.to_string()).collect();
+        args.options.insert(id, values);
+        return Ok(());
+    }
+
+    match verb {
+        "--dir" | "-d" => {
+            // for duplicate directory in file
+            if !args.dir.is_empty() {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+
+            if line_arg.len() > 1 {
+                args.dir = line_arg[1].to_string();
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        "--time-field" => {
+            if args.time_field != TimeField::default() {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            if line_arg.len() > 1 {
+                args.time_field = line_arg[1].parse()?;
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        "--dryrun" | "-n" => {
+            if args.dry_run {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            args.dry_run = true;
+        }
+        "--interactive" => {
+            if args.interactive {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            args.interactive = true;
+        }
+        "--tui" => {
+            if args.tui {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            args.tui = true;
+        }
+        "--trash" => {
+            if args.trash {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            args.trash = true;
+        }
+        "--quarantine" => {
+            if args.quarantine.is_some() {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            if line_arg.len() > 1 {
+                args.quarantine = Some(line_arg[1].to_string());
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        "--archive" => {
+            if args.archive.is_some() {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            if line_arg.len() > 1 {
+                args.archive = Some(line_arg[1].to_string());
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        "--compress" => {
+            if args.compress.is_some() {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            if line_arg.len() > 1 {
+                args.compress = Some(line_arg[1].parse()?);
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        "--shred" => {
+            if args.shred.is_some() {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            if line_arg.len() > 1 {
+                args.shred = Some(line_arg[1].parse()?);
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        "--report-format" => {
+            if line_arg.len() > 1 {
+                args.report_format = line_arg[1].parse()?;
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        "--report-file" => {
+            if args.report_file.is_some() {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            if line_arg.len() > 1 {
+                args.report_file = Some(line_arg[1].to_string());
+                if args.report_format == ReportFormat::Text {
+                    args.report_format = ReportFormat::Json;
+                }
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        "--journal-dir" => {
+            if args.journal_dir.is_some() {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+            if line_arg.len() > 1 {
+                args.journal_dir = Some(line_arg[1].to_string());
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        "--files_to_ignore" | "-i" => {
+            if !args.files_to_ignore.is_empty() {
+                eprint!("Found duplicate verb, {}", verb);
+                return Ok(());
+            }
+
+            if line_arg.len() > 1 {
+                for val in line_arg[1..].iter() {
+                    args.files_to_ignore.push(val.to_string());
+                }
+            } else {
+                eprint!(
+                    "Expected at least one arg for specified verb, {}, skipping...",
+                    verb
+                );
+            }
+        }
+        _ => {
+            eprint!("unknown verb, {}, skipping...", verb);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read_file_and_rebuild_args(args: &mut Args) -> Result<()> {
+    // ensure we don't have any values lurking in args. Config files
+    // takes precedence
+    args.clear();
+    let config_file = args.config_file.clone();
+
+    if config_file.is_none() {
+        eprint!("Config file needed to process this funtion");
+        std::process::exit(1);
+    }
+
+    let file = config_file.as_ref().unwrap();
+    let f = std::fs::File::open(file)?;
+
+    let mut verbs = vec![];
+    for line_val in std::io::BufReader::new(f).lines().map_while(|l| l.ok()) {
+        let line_arg: Vec<&str> = line_val.split(' ').collect();
+        build_args(args, &line_arg)?;
+        if let Some(verb) = line_arg.first() {
+            verbs.push(verb.to_string());
+        }
+    }
+    check_conflicts(&verbs)?;
+
+    if args.dir.is_empty() {
+        eprint!("dir not present in the config file");
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Reject config file verbs that conflict with each other on the command line, such as two
+/// actions, rather than silently picking one of them
+fn check_conflicts(verbs: &[String]) -> Result<()> {
+    let command = cleaning_command(Command::new(APP)).arg(dry_run_arg());
+    let given = |arg: &Arg| arg_verbs(arg).into_iter().find(|v| verbs.contains(v));
+    for arg in command.get_arguments() {
+        let Some(verb) = given(arg) else {
+            continue;
+        };
+        for other in command.get_arg_conflicts_with(arg) {
+            if let Some(other_verb) = given(other) {
+                bail!(
+                    "the argument '{}' cannot be used with '{}'",
+                    verb,
+                    other_verb
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(verbs: &[&str]) -> Result<()> {
+        check_conflicts(&verbs.iter().map(|v| v.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_conflicting_config_verbs_are_rejected() {
+        assert!(check(&["--dir", "--archive", "--dedup", "--dryrun"]).is_ok());
+        assert!(check(&["--dir", "--quarantine", "--archive"]).is_err());
+        assert!(check(&["--trash", "--shred"]).is_err());
+        assert!(check(&["--compress", "--quarantine"]).is_err());
+        assert!(check(&["--interactive", "--tui"]).is_err());
     }
 }
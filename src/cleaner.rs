@@ -0,0 +1,674 @@
+use chrono::Utc;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::error::{Error, Result};
+use crate::features::action::Action;
+use crate::features::cleaner_file_duplicate::{find_duplicate_sets, DedupAction, DedupOptions};
+use crate::features::filter::FilterContext;
+use crate::features::filter_expression::{parse_expression, Expr};
+use crate::features::journal::journal_run;
+use crate::features::path_matcher::PathMatcher;
+use crate::features::plan::{Plan, PlanEntry};
+use crate::features::scanner::{scan_directory, Filters};
+use crate::report::Report;
+
+/// Cleans a directory: every file matching all the filters gets the action applied to it.
+/// Without any filter nothing is touched.
+///
+/// ```no_run
+/// use dir_cleaner_rust::Cleaner;
+///
+/// let report = Cleaner::builder()
+///     .root("target")
+///     .expression("ext(o,d) and older(30d)")
+///     .ignore(["/release"])
+///     .dry_run(true)
+///     .build()?
+///     .run()?;
+/// println!("{} files would be deleted", report.files_deleted);
+/// # Ok::<(), dir_cleaner_rust::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cleaner {
+    root: PathBuf,
+    filters: Filters,
+    action: Action,
+    dry_run: bool,
+    ignore: Vec<String>,
+    /// The filters in the expression language, as recorded in the journal
+    descriptions: Vec<String>,
+    journal: Option<PathBuf>,
+}
+
+/// Builds a [`Cleaner`], see [`Cleaner::builder`]
+#[derive(Debug, Clone, Default)]
+pub struct CleanerBuilder {
+    root: Option<PathBuf>,
+    filters: Vec<Expr>,
+    expressions: Vec<String>,
+    select: Vec<String>,
+    ignore: Vec<String>,
+    /// Values given to the filters' options, see
+    /// [`Filter::options`](crate::features::filter::Filter::options)
+    options: HashMap<String, Vec<String>>,
+    action: Action,
+    dry_run: bool,
+    interactive: bool,
+    tui: bool,
+    journal: Option<PathBuf>,
+}
+
+impl Cleaner {
+    pub fn builder() -> CleanerBuilder {
+        CleanerBuilder::default()
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Walk the directory once, apply the action to every selected file and report what was
+    /// done. Unless it's a dry run, what was done is also written to the journal directory, even
+    /// when the run fails partway.
+    pub fn run(&self) -> Result<Report> {
+        let start = Instant::now();
+        let started_at = Utc::now();
+        let mut report = Report::new();
+        let scanned = scan_directory(
+            &self.root.to_string_lossy().to_string(),
+            &self.filters,
+            &self.action,
+            self.dry_run,
+            &mut report,
+            &self.ignore,
+        );
+        report.total_time_sec = start.elapsed().as_secs();
+
+        journal_run(
+            scanned,
+            self.journal.as_deref().filter(|_| !self.dry_run),
+            started_at,
+            &self.root,
+            self.descriptions.clone(),
+            &mut report,
+        )
+        .map_err(Error::Scan)?;
+        Ok(report)
+    }
+
+    /// Select the files as a dry run would and record them in a plan, to be reviewed and then
+    /// applied with [`Plan::apply`]. Returns the dry run's report along with the plan. Duplicates
+    /// can only be planned for removal, not for linking.
+    pub fn plan(&self) -> Result<(Plan, Report)> {
+        let links_duplicates = match &self.filters.expr {
+            Some(expr) if expr.uses_duplicates() => {
+                DedupOptions::from_context(&self.filters.context)
+                    .map_err(Error::InvalidFilter)?
+                    .action
+                    != DedupAction::Delete
+            }
+            _ => false,
+        };
+        if links_duplicates {
+            return Err(Error::InvalidFilter(anyhow::anyhow!(
+                "a plan can only remove duplicates, not link them with --dedup-action"
+            )));
+        }
+        let dry_run = Cleaner {
+            dry_run: true,
+            journal: None,
+            ..self.clone()
+        };
+        let report = dry_run.run()?;
+        let keepers: HashMap<PathBuf, PathBuf> = report
+            .duplicate_groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .removed
+                    .iter()
+                    .map(|copy| (PathBuf::from(copy), PathBuf::from(&group.keeper)))
+            })
+            .collect();
+        let entries = report
+            .targets
+            .iter()
+            .map(|path| match keepers.get(path) {
+                Some(keeper) => PlanEntry::duplicate(path, keeper),
+                None => PlanEntry::new(path),
+            })
+            .collect::<anyhow::Result<_>>()
+            .map_err(Error::Scan)?;
+        let plan = Plan::new(&self.root, &self.action, self.descriptions.clone(), entries);
+        Ok((plan, report))
+    }
+}
+
+impl CleanerBuilder {
+    /// Directory to clean
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Add a filter that must hold as well as the others
+    pub fn filter(mut self, expr: Expr) -> Self {
+        self.filters.push(expr);
+        self
+    }
+
+    /// Add a filter expression such as `ext(log) and size>10M`, see
+    /// [`parse_expression`](crate::features::filter_expression::parse_expression)
+    pub fn expression(mut self, expression: impl Into<String>) -> Self {
+        self.expressions.push(expression.into());
+        self
+    }
+
+    /// Only clean paths matching one of these patterns, see [`PathMatcher`]
+    pub fn select<S: Into<String>>(mut self, patterns: impl IntoIterator<Item = S>) -> Self {
+        self.select.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Never touch paths matching one of these patterns, see [`PathMatcher`]
+    pub fn ignore<S: Into<String>>(mut self, patterns: impl IntoIterator<Item = S>) -> Self {
+        self.ignore.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Give values to an option of a filter, by the id of its argument, see
+    /// [`Filter::options`](crate::features::filter::Filter::options)
+    pub fn option<S: Into<String>>(
+        mut self,
+        id: impl Into<String>,
+        values: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.options
+            .insert(id.into(), values.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// How duplicates are found and handled when a filter uses `dup()`, the options of
+    /// [`DuplicateFilter`](crate::features::cleaner_file_duplicate::DuplicateFilter)
+    pub fn dedup(mut self, options: DedupOptions) -> Self {
+        self.options.extend(options.to_options());
+        self
+    }
+
+    /// What happens to the selected files, deleting them by default
+    pub fn action(mut self, action: Action) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Only report what would be done
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Ask on stdin which of the selected files to act on before acting, see
+    /// [`confirm`](crate::features::interactive::confirm)
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Choose which of the selected files to act on in a full screen browser before acting, see
+    /// [`browse`](crate::features::tui::browse)
+    pub fn tui(mut self, tui: bool) -> Self {
+        self.tui = tui;
+        self
+    }
+
+    /// Record every run in this directory so it can be undone, see
+    /// [`undo`](crate::features::journal::undo)
+    pub fn journal(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.journal = Some(dir.into());
+        self
+    }
+
+    /// Check the root and parse the filters
+    pub fn build(self) -> Result<Cleaner> {
+        let root = self.root.ok_or(Error::MissingRoot)?;
+        if !root.is_dir() {
+            return Err(Error::NotADirectory(root));
+        }
+        let mut ignore = self.ignore;
+        // a quarantine or archive kept inside the cleaned directory must not be cleaned itself
+        let written = match &self.action {
+            Action::Quarantine(quarantine) => Some((&quarantine.root, "/")),
+            Action::Archive(archive) => Some((&archive.path, "")),
+            _ => None,
+        };
+        if let Some((path, suffix)) = written {
+            if let Some(relative) = relative_to(path, &root) {
+                ignore.push(format!("/{}{}", relative.to_string_lossy(), suffix));
+            }
+        }
+        // validated here so a bad pattern is reported before anything is touched
+        PathMatcher::new(&ignore, Some(&root)).map_err(Error::InvalidPattern)?;
+
+        let mut filters = Filters {
+            context: FilterContext {
+                root: root.clone(),
+                options: self.options,
+                ..Default::default()
+            },
+            interactive: self.interactive,
+            tui: self.tui,
+            ..Default::default()
+        };
+        let mut descriptions = vec![];
+        let mut add = |expr: Expr| {
+            descriptions.push(expr.to_string());
+            filters.and(expr);
+        };
+        for expr in self.filters {
+            add(expr);
+        }
+        if !self.select.is_empty() {
+            let matcher =
+                PathMatcher::new(&self.select, Some(&root)).map_err(Error::InvalidPattern)?;
+            add(Expr::Path(matcher));
+        }
+        for expression in &self.expressions {
+            add(parse_expression(expression).map_err(Error::InvalidFilter)?);
+        }
+
+        // the duplicate options are read when duplicates are grouped, doing so without any
+        // file reports a bad option before anything is touched
+        if let Some(expr) = &filters.expr {
+            find_duplicate_sets(expr, &[], &[], &filters.context).map_err(Error::InvalidFilter)?;
+        }
+
+        Ok(Cleaner {
+            root,
+            filters,
+            action: self.action,
+            dry_run: self.dry_run,
+            ignore,
+            descriptions,
+            journal: self.journal,
+        })
+    }
+}
+
+/// The path relative to the root when it lies inside it, comparing the resolved paths
+fn relative_to(path: &Path, root: &Path) -> Option<PathBuf> {
+    let root = root.canonicalize().ok()?;
+    // the quarantine root may not exist yet, resolve its closest existing ancestor
+    let existing = path.ancestors().find(|dir| dir.exists())?;
+    let resolved = existing
+        .canonicalize()
+        .ok()?
+        .join(path.strip_prefix(existing).ok()?);
+    let relative = resolved.strip_prefix(&root).ok()?;
+    (!relative.as_os_str().is_empty()).then(|| relative.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::journal::Journal;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_builder_runs_the_filters() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let log = temp_dir.path().join("app.log");
+        let kept = temp_dir.path().join("keep.log");
+        let txt = temp_dir.path().join("notes.txt");
+        for path in [&log, &kept, &txt] {
+            fs::write(path, "content")?;
+        }
+
+        let report = Cleaner::builder()
+            .root(temp_dir.path())
+            .filter(Expr::Ext(vec!["log".to_string()]))
+            .ignore(["keep.log"])
+            .build()?
+            .run()?;
+
+        assert!(!log.exists());
+        assert!(kept.exists());
+        assert!(txt.exists());
+        assert_eq!(report.files_scanned, 3);
+        assert_eq!(report.files_deleted, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_quarantine_inside_the_root_is_not_cleaned() -> anyhow::Result<()> {
+        use crate::features::quarantine::{read_manifest, Quarantine};
+
+        let temp_dir = tempdir()?;
+        let log = temp_dir.path().join("logs").join("app.log");
+        fs::create_dir_all(log.parent().unwrap())?;
+        fs::write(&log, "content")?;
+        let quarantine = Quarantine::new(temp_dir.path().join(".quarantine"));
+
+        let cleaner = Cleaner::builder()
+            .root(temp_dir.path())
+            .filter(Expr::Ext(vec!["log".to_string()]))
+            .action(Action::Quarantine(quarantine.clone()))
+            .build()?;
+        let report = cleaner.run()?;
+        assert_eq!(report.files_deleted, 1);
+        assert!(quarantine
+            .root
+            .join(&quarantine.run)
+            .join("logs/app.log")
+            .exists());
+
+        // a second run leaves the quarantined file alone
+        let report = cleaner.run()?;
+        assert_eq!(report.files_deleted, 0);
+        assert_eq!(read_manifest(&quarantine.root)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_runs_are_journaled_and_undone() -> anyhow::Result<()> {
+        use crate::features::journal::undo;
+        use crate::features::quarantine::Quarantine;
+
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path().join("data");
+        let journals = temp_dir.path().join("journal");
+        fs::create_dir_all(&root)?;
+        let log = root.join("app.log");
+        fs::write(&log, "content")?;
+
+        let builder = Cleaner::builder()
+            .root(&root)
+            .expression("ext(log)")
+            .journal(&journals);
+        let report = builder.clone().dry_run(true).build()?.run()?;
+        assert!(report.run_id.is_none());
+        assert!(!journals.exists());
+
+        let quarantine = Quarantine::new(temp_dir.path().join("quarantine"));
+        let report = builder
+            .action(Action::Quarantine(quarantine))
+            .build()?
+            .run()?;
+        let run_id = report.run_id.expect("a journal is written");
+        let journal = Journal::read(&journals, &run_id)?;
+        assert_eq!(journal.filters, vec!["ext(log)"]);
+        assert_eq!(journal.entries.len(), 1);
+        assert!(journal.entries[0].path.is_absolute());
+        assert!(!log.exists());
+
+        let undone = undo(&journals, &run_id, false)?;
+        assert_eq!(undone.undone.len(), 1);
+        assert!(undone.failed.is_empty());
+        assert_eq!(fs::read_to_string(&log)?, "content");
+        assert!(Journal::read(&journals, &run_id).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_archived_run_is_undone() -> anyhow::Result<()> {
+        use crate::features::archive::{read_index, Archive};
+        use crate::features::journal::undo;
+
+        let temp_dir = tempdir()?;
+        let journals = temp_dir.path().join("journal");
+        let log = temp_dir.path().join("logs").join("app.log");
+        fs::create_dir_all(log.parent().unwrap())?;
+        fs::write(&log, "content")?;
+        let archive = Archive::new(temp_dir.path().join("old-logs.tar.gz"))?;
+
+        let report = Cleaner::builder()
+            .root(temp_dir.path())
+            .expression("ext(log) or ext(gz)")
+            .action(Action::Archive(archive.clone()))
+            .journal(&journals)
+            .build()?
+            .run()?;
+        assert_eq!(report.files_deleted, 1);
+        assert!(!log.exists());
+        assert!(read_index(&archive.path)?.contains_key(Path::new("logs/app.log")));
+
+        let undone = undo(&journals, &report.run_id.unwrap(), false)?;
+        assert_eq!(undone.undone.len(), 1);
+        assert_eq!(fs::read_to_string(&log)?, "content");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_failing_partway_is_journaled() -> anyhow::Result<()> {
+        use crate::features::archive::Archive;
+        use crate::features::journal::undo;
+
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path().join("data");
+        let journals = temp_dir.path().join("journal");
+        fs::create_dir_all(&root)?;
+        let keeper = root.join("a.txt");
+        let copy = root.join("b.txt");
+        fs::write(&keeper, "same")?;
+        fs::write(&copy, "same")?;
+        fs::write(root.join("app.log"), "log")?;
+        // the archive is never replaced, so archiving fails after the copy was linked
+        let archive = temp_dir.path().join("logs.tar.gz");
+        fs::write(&archive, "taken")?;
+
+        let failed = Cleaner::builder()
+            .root(&root)
+            .expression("dup() or ext(log)")
+            .dedup(DedupOptions {
+                action: DedupAction::Hardlink,
+                ..Default::default()
+            })
+            .action(Action::Archive(Archive::new(&archive)?))
+            .journal(&journals)
+            .build()?
+            .run();
+        assert!(matches!(failed, Err(Error::Scan(_))));
+
+        let run_id = fs::read_dir(&journals)?
+            .next()
+            .expect("a journal is written")?
+            .path()
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let journal = Journal::read(&journals, &run_id)?;
+        assert_eq!(journal.entries.len(), 1);
+        assert_eq!(journal.entries[0].path, std::path::absolute(&copy)?);
+
+        let undone = undo(&journals, &run_id, false)?;
+        assert_eq!(undone.undone.len(), 1);
+        fs::write(&copy, "changed")?;
+        assert_eq!(fs::read_to_string(&keeper)?, "same");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_run_reports_space_saved() -> anyhow::Result<()> {
+        use crate::features::compress::Compression;
+        use crate::features::journal::undo;
+
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path().join("logs");
+        let journals = temp_dir.path().join("journal");
+        fs::create_dir_all(&root)?;
+        let log = root.join("app.log");
+        let rotated = root.join("app.1.log.gz");
+        fs::write(&log, "line\n".repeat(1000))?;
+        fs::write(&rotated, "already compressed")?;
+
+        let report = Cleaner::builder()
+            .root(&root)
+            .expression("name(*.log*)")
+            .action(Action::Compress(Compression::Gzip))
+            .journal(&journals)
+            .build()?
+            .run()?;
+        assert_eq!(report.files_deleted, 1);
+        assert!(report.total_file_size_saved > 4000);
+        assert!(!log.exists());
+        assert!(root.join("app.log.gz").exists());
+        assert_eq!(fs::read_to_string(&rotated)?, "already compressed");
+
+        undo(&journals, &report.run_id.unwrap(), false)?;
+        assert_eq!(fs::read_to_string(&log)?, "line\n".repeat(1000));
+        assert!(!root.join("app.log.gz").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_is_applied_as_reviewed() -> anyhow::Result<()> {
+        use crate::features::journal::undo;
+        use crate::features::quarantine::Quarantine;
+
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path().join("root");
+        let journals = temp_dir.path().join("journals");
+        fs::create_dir_all(&root)?;
+        let planned = root.join("old.log");
+        fs::write(&planned, "content")?;
+
+        let cleaner = Cleaner::builder()
+            .root(&root)
+            .filter(Expr::Ext(vec!["log".to_string()]))
+            .action(Action::Quarantine(Quarantine::new(
+                temp_dir.path().join("quarantine"),
+            )))
+            .build()?;
+        let (plan, report) = cleaner.plan()?;
+        assert_eq!(report.files_deleted, 1);
+        assert!(planned.exists());
+        assert_eq!(plan.filters, vec!["ext(log)"]);
+
+        // matches the filters too, but wasn't reviewed
+        let unplanned = root.join("new.log");
+        fs::write(&unplanned, "content")?;
+        let applied = plan.apply(Some(&journals), false)?;
+        assert!(!planned.exists());
+        assert!(unplanned.exists());
+        assert!(applied.changed.is_empty());
+
+        undo(&journals, &applied.report.run_id.unwrap(), false)?;
+        assert!(planned.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_planned_duplicates_need_their_keeper() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let keeper = temp_dir.path().join("a.txt");
+        let copy = temp_dir.path().join("b.txt");
+        fs::write(&keeper, "content")?;
+        fs::write(&copy, "content")?;
+        let old = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(&keeper, old)?;
+
+        let (plan, _) = Cleaner::builder()
+            .root(temp_dir.path())
+            .expression("dup()")
+            .build()?
+            .plan()?;
+        assert_eq!(plan.entries.len(), 1);
+        let planned_keeper = plan.entries[0].keeper.as_ref().unwrap();
+        assert_eq!(planned_keeper.path, std::path::absolute(&keeper)?);
+
+        // the copy would be the last one left
+        fs::write(&keeper, "changed")?;
+        let applied = plan.apply(None, false)?;
+        assert!(copy.exists());
+        assert!(applied.changed[0].1.contains("keeper"));
+
+        let linked = Cleaner::builder()
+            .root(temp_dir.path())
+            .expression("dup()")
+            .dedup(DedupOptions {
+                action: DedupAction::Hardlink,
+                ..Default::default()
+            })
+            .build()?
+            .plan();
+        assert!(matches!(linked, Err(Error::InvalidFilter(_))));
+
+        let outside = Cleaner::builder()
+            .root(temp_dir.path())
+            .expression("dup()")
+            .dedup(DedupOptions {
+                keep: crate::features::cleaner_file_duplicate::KeepPolicy::PreferDir(
+                    std::env::temp_dir(),
+                ),
+                ..Default::default()
+            })
+            .build();
+        assert!(matches!(outside, Err(Error::InvalidFilter(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignored_paths_are_resolved_against_the_root() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let data = temp_dir.path().join("data");
+        fs::create_dir(&data)?;
+        let keep = data.join("keep.txt");
+        let other = data.join("other.txt");
+        fs::write(&keep, "keep")?;
+        fs::write(&other, "other")?;
+        fs::write(temp_dir.path().join("outside.txt"), "outside")?;
+
+        Cleaner::builder()
+            .root(temp_dir.path().join(".").join("data"))
+            .expression("ext(txt)")
+            .ignore([keep.canonicalize()?.to_string_lossy().to_string()])
+            .build()?
+            .run()?;
+        assert!(keep.exists());
+        assert!(!other.exists());
+
+        let outside = temp_dir.path().join("outside.txt");
+        assert!(matches!(
+            Cleaner::builder()
+                .root(&data)
+                .ignore([outside.to_string_lossy().to_string()])
+                .build(),
+            Err(Error::InvalidPattern(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_reports_typed_errors() {
+        assert!(matches!(
+            Cleaner::builder().build(),
+            Err(Error::MissingRoot)
+        ));
+        assert!(matches!(
+            Cleaner::builder().root("/does/not/exist").build(),
+            Err(Error::NotADirectory(_))
+        ));
+        assert!(matches!(
+            Cleaner::builder()
+                .root(".")
+                .expression("ext(log) and")
+                .build(),
+            Err(Error::InvalidFilter(_))
+        ));
+        assert!(matches!(
+            Cleaner::builder().root(".").select(["re:("]).build(),
+            Err(Error::InvalidPattern(_))
+        ));
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Errors returned by the [`Cleaner`](crate::Cleaner)
+#[derive(Debug)]
+pub enum Error {
+    /// No directory to clean was given to the builder
+    MissingRoot,
+    /// The directory to clean doesn't exist or isn't a directory
+    NotADirectory(PathBuf),
+    /// A filter expression couldn't be parsed
+    InvalidFilter(anyhow::Error),
+    /// A select or ignore pattern couldn't be compiled
+    InvalidPattern(anyhow::Error),
+    /// Walking the directory or acting on a file failed
+    Scan(anyhow::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingRoot => write!(f, "No directory to clean was given"),
+            Error::NotADirectory(path) => write!(f, "Not a directory: {:?}", path),
+            Error::InvalidFilter(err) => write!(f, "Invalid filter: {:#}", err),
+            Error::InvalidPattern(err) => write!(f, "Invalid pattern: {:#}", err),
+            Error::Scan(err) => write!(f, "{:#}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidFilter(err) | Error::InvalidPattern(err) | Error::Scan(err) => {
+                Some(err.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
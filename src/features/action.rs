@@ -0,0 +1,132 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::archive::Archive;
+use super::compress::Compression;
+use super::journal::Operation;
+use super::quarantine::Quarantine;
+use super::scanner::FileRecord;
+use super::trash::trash_file;
+use super::utils::{can_shred, delete_file, shred_file, Shred};
+
+/// What happens to the files selected by the filters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Remove the file
+    #[default]
+    Delete,
+    /// Move the file to the freedesktop.org trash, from where it can be restored
+    Trash,
+    /// Move the file into a quarantine root, from where it can be restored or purged
+    Quarantine(Quarantine),
+    /// Stream the files into a compressed archive, deleting them once it's verified
+    Archive(Archive),
+    /// Replace the file with a compressed sibling, `app.log` becomes `app.log.zst`
+    Compress(Compression),
+    /// Overwrite the file before unlinking it
+    Shred(Shred),
+}
+
+impl Action {
+    /// What happened to the files the action was applied to, as shown in the report
+    pub fn past_tense(&self) -> &'static str {
+        match self {
+            Action::Delete => "Deleted",
+            Action::Trash => "Trashed",
+            Action::Quarantine(_) => "Quarantined",
+            Action::Archive(_) => "Archived",
+            Action::Compress(_) => "Compressed",
+            Action::Shred(_) => "Shredded",
+        }
+    }
+
+    /// Name of the action, as given on the command line and in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Delete => "delete",
+            Action::Trash => "trash",
+            Action::Quarantine(_) => "quarantine",
+            Action::Archive(_) => "archive",
+            Action::Compress(_) => "compress",
+            Action::Shred(_) => "shred",
+        }
+    }
+
+    /// Whether the action can be applied to a file the filters selected, files it can't be
+    /// applied to are retained
+    pub fn accepts(&self, record: &FileRecord) -> bool {
+        match self {
+            Action::Compress(compression) => compression.can_compress(&record.path),
+            Action::Shred(_) => can_shred(&record.path),
+            _ => true,
+        }
+    }
+
+    /// Apply the action to the selected files, returning what was done to each for the
+    /// journal, or why it failed. A dry run only reports what would happen, returning the
+    /// operations that can be foreseen without touching the disk. Only an archive fails as a
+    /// whole, since its files go into it together.
+    pub fn apply(
+        &self,
+        records: &[&FileRecord],
+        dry_run: bool,
+    ) -> Result<Vec<Result<Option<Operation>>>> {
+        let Action::Archive(archive) = self else {
+            return Ok(records
+                .iter()
+                .map(|record| self.apply_to_file(record, dry_run))
+                .collect());
+        };
+        // the whole set goes into a single archive
+        let names = archive.archive_files(records, dry_run)?;
+        if names.is_empty() {
+            return Ok(records.iter().map(|_| Ok(None)).collect());
+        }
+        Ok(names
+            .into_iter()
+            .map(|entry| {
+                entry.map(|entry| {
+                    Some(Operation::Archive {
+                        archive: archive.path.clone(),
+                        entry,
+                    })
+                })
+            })
+            .collect())
+    }
+
+    fn apply_to_file(&self, record: &FileRecord, dry_run: bool) -> Result<Option<Operation>> {
+        Ok(match self {
+            Action::Delete => {
+                delete_file(&record.path, dry_run)?;
+                (!dry_run).then_some(Operation::Delete)
+            }
+            Action::Trash => trash_file(&record.path, dry_run)?
+                .map(|trashed_path| Operation::Trash { trashed_path }),
+            Action::Quarantine(quarantine) => {
+                quarantine
+                    .quarantine_file(record, dry_run)?
+                    .map(|quarantined_path| Operation::Quarantine {
+                        quarantine: quarantine.root.clone(),
+                        quarantined_path,
+                    })
+            }
+            Action::Compress(compression) => {
+                let (compressed_path, compressed_size) =
+                    compression.compress_file(&record.path, dry_run)?;
+                Some(Operation::Compress {
+                    compressed_path,
+                    compressed_size,
+                })
+            }
+            Action::Shred(shred) => {
+                shred_file(&record.path, shred, dry_run)?;
+                (!dry_run).then_some(Operation::Shred {
+                    passes: shred.passes.len(),
+                })
+            }
+            Action::Archive(_) => unreachable!("archives are written for the whole set"),
+        })
+    }
+}
@@ -0,0 +1,431 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use colored::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::cleaner_file_duplicate::find_duplicate_sets;
+use super::cleaner_file_type::TypeFilter;
+use super::cleaner_last_modified_time::AgeFilter;
+use super::filter::{verbs, Filter, FilterContext};
+use super::filter_expression::Expr;
+use super::ignore_files::IgnoreFiles;
+use super::path_matcher::PathMatcher;
+use super::scanner::{walk_directory, FileRecord};
+use super::utils::format_size;
+
+/// How many extensions are suggested as candidates when none is given
+const SUGGESTED_EXTENSIONS: usize = 5;
+
+/// Ages suggested as candidates when none is given
+const SUGGESTED_AGES: &[&str] = &["30d", "90d", "1y"];
+
+/// Width of the bars drawn next to the largest directories and extensions
+const BAR_WIDTH: usize = 20;
+
+/// A filter whose savings are estimated, labelled the way it's given on the command line
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    /// Such as `-t log`
+    pub label: String,
+    pub expr: Expr,
+}
+
+impl Candidate {
+    /// The candidate for a registered filter given these values, `None` when they disable it
+    pub fn from_filter(
+        filter: &dyn Filter,
+        values: &[String],
+        context: &FilterContext,
+    ) -> Result<Option<Candidate>> {
+        let verb = verbs(filter).pop().unwrap_or_default();
+        Ok(filter.parse(values, context)?.map(|expr| Candidate {
+            label: [verb]
+                .into_iter()
+                .chain(values.to_vec())
+                .collect::<Vec<_>>()
+                .join(" "),
+            expr,
+        }))
+    }
+}
+
+/// What a candidate would select if the cleaner ran with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Savings {
+    pub label: String,
+    pub files: u32,
+    pub bytes: u64,
+}
+
+/// A file or directory and its size, cumulative for a directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Usage {
+    pub path: PathBuf,
+    pub files: u32,
+    pub bytes: u64,
+}
+
+/// Where the space of a directory goes, without touching anything
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub root: PathBuf,
+    pub files_scanned: u32,
+    pub total_size: u64,
+    pub largest_files: Vec<Usage>,
+    /// Directories under the root with everything below them, largest first
+    pub largest_directories: Vec<Usage>,
+    pub oldest_files: Vec<(PathBuf, DateTime<Utc>)>,
+    /// Files and bytes per extension, largest first, the path holds the extension
+    pub extensions: Vec<Usage>,
+    /// Estimated savings of the candidates given, or of suggested ones when none was
+    pub savings: Vec<Savings>,
+    /// Whether the candidates were suggested rather than given
+    pub suggested: bool,
+}
+
+/// Walk the directory the way the cleaners do and sum up where its space goes, keeping the top
+/// `top` entries of each list. Each candidate's savings count the files it would select; files
+/// protected by an ignore pattern or ignore file take space but are never counted as savings.
+/// When more than one candidate is given, the savings of all of them together, as the cleaner
+/// would combine them, come last.
+pub fn analyze_directory(
+    directory: &String,
+    candidates: &[Candidate],
+    paths_to_ignore: &[String],
+    top: usize,
+) -> Result<Analysis> {
+    let root = Path::new(directory);
+    let ignore = PathMatcher::new(paths_to_ignore, Some(root))?;
+    let read_git_files = candidates.iter().any(|c| c.expr.uses_git_ignores());
+    let mut ignore_files = IgnoreFiles::new(root, read_git_files);
+    let records = walk_directory(directory, &ignore, &mut ignore_files)?;
+    let protected: Vec<bool> = records
+        .iter()
+        .map(|r| {
+            ignore.is_match(&r.relative_path, false)
+                || ignore_files.is_protected(&r.relative_path, false)
+        })
+        .collect();
+
+    let mut analysis = Analysis {
+        root: root.to_path_buf(),
+        files_scanned: records.len() as u32,
+        total_size: records.iter().map(|r| r.metadata.len()).sum(),
+        ..Default::default()
+    };
+
+    let mut largest_files: Vec<Usage> = records
+        .iter()
+        .map(|r| Usage {
+            path: r.path.clone(),
+            files: 1,
+            bytes: r.metadata.len(),
+        })
+        .collect();
+    analysis.largest_files = largest(&mut largest_files, top);
+
+    let mut directories: HashMap<PathBuf, Usage> = HashMap::new();
+    let mut extensions: HashMap<PathBuf, Usage> = HashMap::new();
+    for record in &records {
+        for dir in record.relative_path.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() {
+                continue;
+            }
+            add(&mut directories, root.join(dir), record.metadata.len());
+        }
+        let extension = record
+            .path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+            .unwrap_or_else(|| "(none)".to_string());
+        add(
+            &mut extensions,
+            PathBuf::from(extension),
+            record.metadata.len(),
+        );
+    }
+    analysis.largest_directories = largest(&mut directories.into_values().collect(), top);
+    analysis.extensions = largest(&mut extensions.into_values().collect(), top);
+
+    let mut oldest: Vec<(PathBuf, DateTime<Utc>)> = records
+        .iter()
+        .filter_map(|r| {
+            let modified = r.metadata.modified().ok()?;
+            Some((r.path.clone(), DateTime::<Utc>::from(modified)))
+        })
+        .collect();
+    oldest.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    oldest.truncate(top);
+    analysis.oldest_files = oldest;
+
+    let mut candidates = candidates.to_vec();
+    if candidates.len() > 1 {
+        let all = candidates
+            .iter()
+            .map(|c| c.expr.clone())
+            .reduce(|all, expr| all.and(expr));
+        if let Some(expr) = all {
+            let label = candidates
+                .iter()
+                .map(|c| c.label.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            candidates.push(Candidate { label, expr });
+        }
+    } else if candidates.is_empty() {
+        candidates = suggest(&analysis, root)?;
+        analysis.suggested = true;
+    }
+    // duplicates are found with the default options of the filter
+    let context = FilterContext {
+        root: root.to_path_buf(),
+        ..Default::default()
+    };
+    for candidate in &candidates {
+        analysis
+            .savings
+            .push(estimate(candidate, &records, &protected, &context)?);
+    }
+
+    Ok(analysis)
+}
+
+fn add(usage: &mut HashMap<PathBuf, Usage>, path: PathBuf, bytes: u64) {
+    let entry = usage.entry(path.clone()).or_insert(Usage {
+        path,
+        files: 0,
+        bytes: 0,
+    });
+    entry.files += 1;
+    entry.bytes += bytes;
+}
+
+fn largest(usage: &mut Vec<Usage>, top: usize) -> Vec<Usage> {
+    usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+    usage.truncate(top);
+    std::mem::take(usage)
+}
+
+/// The largest extensions and a few ages, through the same filters as `-t` and `-a`
+fn suggest(analysis: &Analysis, root: &Path) -> Result<Vec<Candidate>> {
+    let context = FilterContext {
+        root: root.to_path_buf(),
+        ..Default::default()
+    };
+    let mut candidates = vec![];
+    for usage in analysis.extensions.iter().take(SUGGESTED_EXTENSIONS) {
+        let Some(extension) = usage
+            .path
+            .to_string_lossy()
+            .strip_prefix('.')
+            .map(String::from)
+        else {
+            continue;
+        };
+        candidates.extend(Candidate::from_filter(&TypeFilter, &[extension], &context)?);
+    }
+    for age in SUGGESTED_AGES {
+        candidates.extend(Candidate::from_filter(
+            &AgeFilter,
+            &[age.to_string()],
+            &context,
+        )?);
+    }
+    Ok(candidates)
+}
+
+/// What the candidate would select, with duplicates found the way the cleaner finds them
+fn estimate(
+    candidate: &Candidate,
+    records: &[FileRecord],
+    protected: &[bool],
+    context: &FilterContext,
+) -> Result<Savings> {
+    let expr = &candidate.expr;
+    expr.prepare(records)?;
+    let mut is_duplicate = vec![false; records.len()];
+    let eligible: Vec<bool> = protected.iter().map(|p| !p).collect();
+    for set in find_duplicate_sets(expr, records, &eligible, context)? {
+        for idx in set.copies {
+            is_duplicate[idx] = true;
+        }
+    }
+
+    let mut savings = Savings {
+        label: candidate.label.clone(),
+        files: 0,
+        bytes: 0,
+    };
+    for (idx, record) in records.iter().enumerate() {
+        if !protected[idx] && expr.eval(record, is_duplicate[idx]) {
+            savings.files += 1;
+            savings.bytes += record.metadata.len();
+        }
+    }
+    Ok(savings)
+}
+
+impl Analysis {
+    /// Print the analysis to stdout
+    pub fn print(&self) {
+        println!("{}", "Disk Usage Analysis".bold().underline().blue());
+        println!();
+        println!(
+            "{}: {}",
+            "Directory".bold().cyan(),
+            self.root.display().to_string().green()
+        );
+        println!(
+            "{}: {}",
+            "Files Scanned".bold().cyan(),
+            self.files_scanned.to_string().green()
+        );
+        println!(
+            "{}: {}",
+            "Total File Size".bold().cyan(),
+            format_size(self.total_size).green()
+        );
+
+        if !self.largest_directories.is_empty() {
+            println!("\n{}", "Largest Directories".bold().blue());
+            self.print_usage(&self.largest_directories);
+        }
+
+        if !self.largest_files.is_empty() {
+            println!("\n{}", "Largest Files".bold().blue());
+            for usage in &self.largest_files {
+                println!(
+                    "{:>10}  {}",
+                    format_size(usage.bytes).yellow(),
+                    usage.path.display()
+                );
+            }
+        }
+
+        if !self.oldest_files.is_empty() {
+            println!("\n{}", "Oldest Files".bold().blue());
+            for (path, modified) in &self.oldest_files {
+                println!(
+                    "{}  {}",
+                    modified.format("%Y-%m-%d").to_string().yellow(),
+                    path.display()
+                );
+            }
+        }
+
+        if !self.extensions.is_empty() {
+            println!("\n{}", "Extensions".bold().blue());
+            self.print_usage(&self.extensions);
+        }
+
+        if !self.savings.is_empty() {
+            println!(
+                "\n{}",
+                if self.suggested {
+                    "Estimated Savings Of Suggested Filters"
+                } else {
+                    "Estimated Savings"
+                }
+                .bold()
+                .blue()
+            );
+            for savings in &self.savings {
+                println!(
+                    "{} would free {} in {} files",
+                    savings.label.bold(),
+                    format_size(savings.bytes).red(),
+                    savings.files
+                );
+            }
+        }
+    }
+
+    /// Sizes with a bar relative to the whole directory, like ncdu
+    fn print_usage(&self, usage: &[Usage]) {
+        for usage in usage {
+            let filled = (usage.bytes as f64 / self.total_size.max(1) as f64 * BAR_WIDTH as f64)
+                .round() as usize;
+            println!(
+                "{:>10} [{}{}] {:>6} files  {}",
+                format_size(usage.bytes).yellow(),
+                "#".repeat(filled).red(),
+                " ".repeat(BAR_WIDTH - filled.min(BAR_WIDTH)),
+                usage.files,
+                usage.path.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::filter_expression::parse_expression;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_analyze_sums_up_usage_and_savings() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("logs/old"))?;
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(root.join("logs/app.log"), vec![0; 3000])?;
+        fs::write(root.join("logs/old/app.log"), vec![1; 2000])?;
+        fs::write(root.join("src/main.rs"), vec![2; 500])?;
+        fs::write(root.join("keep.log"), vec![3; 100])?;
+        fs::write(root.join("copy.rs"), vec![2; 500])?;
+        let old = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(root.join("logs/old/app.log"), old)?;
+
+        let directory = root.to_string_lossy().to_string();
+        let candidate = |label: &str, expression: &str| -> Result<Candidate> {
+            Ok(Candidate {
+                label: label.to_string(),
+                expr: parse_expression(expression)?,
+            })
+        };
+        let analysis = analyze_directory(
+            &directory,
+            &[
+                candidate("-t log", "ext(log)")?,
+                candidate("-r true", "dup()")?,
+            ],
+            &["/keep.log".to_string()],
+            2,
+        )?;
+
+        assert_eq!(analysis.files_scanned, 5);
+        assert_eq!(analysis.total_size, 6100);
+        assert_eq!(analysis.largest_files[0].path, root.join("logs/app.log"));
+        assert_eq!(analysis.largest_files.len(), 2);
+        assert_eq!(
+            analysis.largest_directories[0],
+            Usage {
+                path: root.join("logs"),
+                files: 2,
+                bytes: 5000,
+            }
+        );
+        assert_eq!(analysis.largest_directories[1].path, root.join("logs/old"));
+        assert_eq!(analysis.oldest_files[0].0, root.join("logs/old/app.log"));
+        assert_eq!(analysis.extensions[0].path, PathBuf::from(".log"));
+        assert_eq!(analysis.extensions[0].files, 3);
+
+        // keep.log is protected, one of the two identical .rs files is kept
+        let savings = |label: &str| analysis.savings.iter().find(|s| s.label == label).cloned();
+        assert_eq!(savings("-t log").unwrap().bytes, 5000);
+        assert_eq!(savings("-r true").unwrap().files, 1);
+        assert_eq!(savings("-t log -r true").unwrap().files, 0);
+        assert!(!analysis.suggested);
+
+        let analysis = analyze_directory(&directory, &[], &[], 10)?;
+        assert!(analysis.suggested);
+        assert_eq!(analysis.savings[0].label, "-t log");
+        assert_eq!(analysis.savings[0].bytes, 5100);
+        assert!(analysis.savings.iter().any(|s| s.label == "-a 1y"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,455 @@
+use anyhow::{bail, Context, Result};
+use chrono::{Datelike, Local, NaiveDate, TimeZone, Timelike};
+use colored::*;
+use filetime::{set_file_mtime, FileTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use super::scanner::FileRecord;
+use super::utils::delete_file;
+
+/// Compression format of an archive, picked from its file name
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    /// `.tar.gz` or `.tgz`
+    TarGz,
+    /// `.tar.zst` or `.tzst`
+    TarZst,
+    /// `.zip`
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Ok(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Ok(ArchiveFormat::TarZst)
+        } else if name.ends_with(".zip") {
+            Ok(ArchiveFormat::Zip)
+        } else {
+            bail!(
+                "Unknown archive format: {:?}, expected .tar.gz, .tar.zst or .zip",
+                path
+            )
+        }
+    }
+}
+
+/// Streams the selected files into a compressed archive, keeping their paths relative to the
+/// cleaned directory, their permissions and modification times. The files are only deleted
+/// once the archive has been read back and holds every one of them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Archive {
+    pub path: PathBuf,
+    pub format: ArchiveFormat,
+}
+
+impl Archive {
+    /// Archive into a new file, the format is picked from its extension
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let format = ArchiveFormat::from_path(&path)?;
+        Ok(Archive { path, format })
+    }
+
+    /// Write the files to the archive, verify it and delete them, returning for each file its
+    /// name in the archive, or why it couldn't be deleted once archived. Fails as a whole when
+    /// the archive can't be written or verified, before any file is deleted. An existing archive
+    /// is never replaced.
+    pub fn archive_files(
+        &self,
+        records: &[&FileRecord],
+        dry_run: bool,
+    ) -> Result<Vec<Result<PathBuf>>> {
+        if dry_run {
+            for record in records {
+                if let Some(pth) = record.path.to_str() {
+                    eprintln!(
+                        "\n {} could have been archived into {}",
+                        pth.bold().yellow(),
+                        self.path.display()
+                    );
+                }
+            }
+            return Ok(vec![]);
+        }
+        if records.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let names: Vec<PathBuf> = records.iter().map(|record| entry_name(record)).collect();
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to create archive: {:?}", self.path))?;
+        let written = self
+            .write(file, records, &names)
+            .and_then(|_| self.verify(records, &names));
+        if let Err(err) = written {
+            let _ = fs::remove_file(&self.path);
+            return Err(err.context(format!("Failed to archive files into {:?}", self.path)));
+        }
+
+        // a file that can't be deleted stays in place, the others are archived and gone
+        Ok(records
+            .iter()
+            .zip(names)
+            .map(|(record, name)| delete_file(&record.path, false).map(|_| name))
+            .collect())
+    }
+
+    fn write(&self, file: File, records: &[&FileRecord], names: &[PathBuf]) -> Result<()> {
+        let file = match self.format {
+            ArchiveFormat::TarGz => {
+                let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                write_tar(encoder, records, names)?.finish()?
+            }
+            ArchiveFormat::TarZst => {
+                let encoder = zstd::Encoder::new(file, 0)?;
+                write_tar(encoder, records, names)?.finish()?
+            }
+            ArchiveFormat::Zip => write_zip(file, records, names)?,
+        };
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Read the archive back and check it holds every file with its full size
+    fn verify(&self, records: &[&FileRecord], names: &[PathBuf]) -> Result<()> {
+        let index = read_index(&self.path)?;
+        for (record, name) in records.iter().zip(names) {
+            match index.get(name) {
+                Some(size) if *size == record.metadata.len() => {}
+                Some(size) => bail!(
+                    "{:?} has {} bytes in the archive instead of {}",
+                    name,
+                    size,
+                    record.metadata.len()
+                ),
+                None => bail!("{:?} is missing from the archive", name),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The file's path in the archive, relative to the cleaned directory
+fn entry_name(record: &FileRecord) -> PathBuf {
+    match record.relative_path.file_name() {
+        Some(_) => record.relative_path.clone(),
+        None => PathBuf::from(record.path.file_name().unwrap_or(record.path.as_os_str())),
+    }
+}
+
+fn write_tar<W: Write>(writer: W, records: &[&FileRecord], names: &[PathBuf]) -> Result<W> {
+    // headers carry the permissions and modification time of each file
+    let mut builder = tar::Builder::new(writer);
+    for (record, name) in records.iter().zip(names) {
+        builder
+            .append_path_with_name(&record.path, name)
+            .with_context(|| format!("Failed to archive file: {:?}", record.path))?;
+    }
+    Ok(builder.into_inner()?)
+}
+
+fn write_zip(file: File, records: &[&FileRecord], names: &[PathBuf]) -> Result<File> {
+    let mut zip = zip::ZipWriter::new(file);
+    for (record, name) in records.iter().zip(names) {
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .large_file(record.metadata.len() >= u32::MAX as u64);
+        if let Some(modified) = record.metadata.modified().ok().and_then(zip_time) {
+            options = options.last_modified_time(modified);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            options = options.unix_permissions(record.metadata.permissions().mode());
+        }
+        zip.start_file(zip_name(name), options)?;
+        let mut source = File::open(&record.path)
+            .with_context(|| format!("Failed to archive file: {:?}", record.path))?;
+        io::copy(&mut source, &mut zip)?;
+    }
+    Ok(zip.finish()?)
+}
+
+/// Zip entries always use `/` as the separator
+fn zip_name(name: &Path) -> String {
+    name.to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/")
+}
+
+/// Zip stores local time, without a time zone
+fn zip_time(time: std::time::SystemTime) -> Option<zip::DateTime> {
+    let time = chrono::DateTime::<Local>::from(time);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(time.year()).ok()?,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+    .ok()
+}
+
+fn from_zip_time(time: zip::DateTime) -> Option<FileTime> {
+    let naive =
+        NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+            .and_hms_opt(
+                time.hour().into(),
+                time.minute().into(),
+                time.second().into(),
+            )?;
+    let local = Local.from_local_datetime(&naive).earliest()?;
+    Some(FileTime::from_unix_time(local.timestamp(), 0))
+}
+
+fn open_tar(path: &Path, format: ArchiveFormat) -> Result<tar::Archive<Box<dyn Read>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open archive: {:?}", path))?;
+    let reader: Box<dyn Read> = match format {
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        ArchiveFormat::TarZst => Box::new(zstd::Decoder::new(file)?),
+        ArchiveFormat::Zip => bail!("Not a tar archive: {:?}", path),
+    };
+    Ok(tar::Archive::new(reader))
+}
+
+/// The size of every file in the archive, by name
+pub fn read_index(path: &Path) -> Result<HashMap<PathBuf, u64>> {
+    let mut index = HashMap::new();
+    match ArchiveFormat::from_path(path)? {
+        ArchiveFormat::Zip => {
+            let file =
+                File::open(path).with_context(|| format!("Failed to open archive: {:?}", path))?;
+            let mut zip = zip::ZipArchive::new(file)?;
+            for idx in 0..zip.len() {
+                let entry = zip.by_index(idx)?;
+                index.insert(PathBuf::from(entry.name()), entry.size());
+            }
+        }
+        format => {
+            for entry in open_tar(path, format)?.entries()? {
+                let entry = entry?;
+                index.insert(entry.path()?.to_path_buf(), entry.header().size()?);
+            }
+        }
+    }
+    Ok(index)
+}
+
+/// Extract files from the archive to the given destinations, keeping their permissions and
+/// modification times. A destination that exists is never overwritten. Returns the names of
+/// the extracted files.
+pub fn extract(
+    path: &Path,
+    destinations: &HashMap<PathBuf, PathBuf>,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>> {
+    let mut extracted = vec![];
+    let mut wanted = |name: &Path| -> Option<&PathBuf> {
+        let destination = destinations.get(name)?;
+        if fs::symlink_metadata(destination).is_ok() {
+            eprintln!(
+                "Not extracting {:?}, the path exists again, keeping it in the archive",
+                destination
+            );
+            return None;
+        }
+        if dry_run {
+            eprintln!(
+                "\n {} could have been extracted",
+                destination.display().to_string().bold().yellow()
+            );
+            extracted.push(name.to_path_buf());
+            return None;
+        }
+        if let Some(parent) = destination.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        Some(destination)
+    };
+
+    let mut done = vec![];
+    match ArchiveFormat::from_path(path)? {
+        ArchiveFormat::Zip => {
+            let file =
+                File::open(path).with_context(|| format!("Failed to open archive: {:?}", path))?;
+            let mut zip = zip::ZipArchive::new(file)?;
+            for idx in 0..zip.len() {
+                let mut entry = zip.by_index(idx)?;
+                let name = PathBuf::from(entry.name());
+                let Some(destination) = wanted(&name) else {
+                    continue;
+                };
+                let mut target = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(destination)
+                    .with_context(|| format!("Failed to extract file: {:?}", destination))?;
+                io::copy(&mut entry, &mut target)?;
+                drop(target);
+                #[cfg(unix)]
+                if let Some(mode) = entry.unix_mode() {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(destination, fs::Permissions::from_mode(mode))?;
+                }
+                if let Some(modified) = entry.last_modified().and_then(from_zip_time) {
+                    set_file_mtime(destination, modified)?;
+                }
+                done.push(name);
+            }
+        }
+        format => {
+            let mut archive = open_tar(path, format)?;
+            archive.set_preserve_permissions(true);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let name = entry.path()?.to_path_buf();
+                let Some(destination) = wanted(&name) else {
+                    continue;
+                };
+                entry
+                    .unpack(destination)
+                    .with_context(|| format!("Failed to extract file: {:?}", destination))?;
+                done.push(name);
+            }
+        }
+    }
+    extracted.extend(done);
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn records(root: &Path, names: &[&str]) -> Result<Vec<FileRecord>> {
+        let mut records = vec![];
+        for name in names {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(&path, name.repeat(100))?;
+            set_file_mtime(&path, FileTime::from_unix_time(1_600_000_000, 0))?;
+            records.push(FileRecord {
+                metadata: fs::metadata(&path)?,
+                path,
+                relative_path: PathBuf::from(name),
+                git_ignored: false,
+            });
+        }
+        Ok(records)
+    }
+
+    #[test]
+    fn test_format_from_extension() {
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("old.tar.gz")).unwrap(),
+            ArchiveFormat::TarGz
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("old.TZST")).unwrap(),
+            ArchiveFormat::TarZst
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("old.zip")).unwrap(),
+            ArchiveFormat::Zip
+        );
+        assert!(ArchiveFormat::from_path(Path::new("old.rar")).is_err());
+    }
+
+    #[test]
+    fn test_archive_round_trip() -> Result<()> {
+        for archive_name in ["logs.tar.gz", "logs.tar.zst", "logs.zip"] {
+            let temp_dir = tempdir()?;
+            let data = temp_dir.path().join("data");
+            let records = records(&data, &["app.log", "nested/dir/old.log"])?;
+            let refs: Vec<&FileRecord> = records.iter().collect();
+            let archive = Archive::new(temp_dir.path().join(archive_name))?;
+
+            let names = archive
+                .archive_files(&refs, false)?
+                .into_iter()
+                .collect::<Result<Vec<_>>>()?;
+            assert_eq!(
+                names,
+                vec![
+                    PathBuf::from("app.log"),
+                    PathBuf::from("nested/dir/old.log")
+                ]
+            );
+            assert!(records.iter().all(|record| !record.path.exists()));
+            assert_eq!(read_index(&archive.path)?.len(), 2);
+
+            // an existing archive is never replaced
+            assert!(archive.archive_files(&refs, false).is_err());
+            assert!(archive.path.exists());
+
+            let destinations = names
+                .iter()
+                .map(|name| (name.clone(), data.join(name)))
+                .collect();
+            assert_eq!(extract(&archive.path, &destinations, false)?.len(), 2);
+            let restored = &records[1].path;
+            assert_eq!(
+                fs::read_to_string(restored)?,
+                "nested/dir/old.log".repeat(100)
+            );
+            assert_eq!(
+                FileTime::from_last_modification_time(&fs::metadata(restored)?).unix_seconds(),
+                1_600_000_000,
+                "{}",
+                archive_name
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_failing_to_delete_are_reported_one_by_one() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let records = records(temp_dir.path(), &["app.log", "old.log"])?;
+        // the same file under a second name is gone by the time it's deleted again
+        let again = FileRecord {
+            path: records[0].path.clone(),
+            relative_path: PathBuf::from("again.log"),
+            metadata: records[0].metadata.clone(),
+            git_ignored: false,
+        };
+        let archive = Archive::new(temp_dir.path().join("logs.tar.gz"))?;
+
+        let results = archive.archive_files(&[&records[0], &again, &records[1]], false)?;
+        assert_eq!(results[0].as_ref().unwrap(), &PathBuf::from("app.log"));
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap(), &PathBuf::from("old.log"));
+        assert!(!records[1].path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_writes_nothing() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let records = records(temp_dir.path(), &["app.log"])?;
+        let archive = Archive::new(temp_dir.path().join("logs.zip"))?;
+        assert!(archive.archive_files(&[&records[0]], true)?.is_empty());
+        assert!(!archive.path.exists());
+        assert!(records[0].path.exists());
+        Ok(())
+    }
+}
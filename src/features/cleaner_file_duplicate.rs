@@ -1,75 +1,943 @@
 use anyhow::{Context, Result};
-use std::collections::HashSet;
-use std::fs;
-use std::path::PathBuf;
-use walkdir::WalkDir;
+use clap::Arg;
+use colored::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use crate::ReportData;
+use crate::report::Report;
 
-use super::utils::{collect_metrics, delete_file};
+use super::action::Action;
+use super::filter::{flag_enabled, Filter, FilterContext};
+use super::filter_expression::Expr;
+use super::scanner::{scan_directory, FileRecord, Filters};
 
-fn get_file_key(file_name: Option<&std::ffi::OsStr>, file_size: u64) -> Option<String> {
-    file_name.and_then(|name| name.to_str()).map(|name| {
-        // Extract the first part of the file name, before any whitespace or special characters
-        let first_part = name.split_whitespace().next().unwrap_or("");
-        format!("{}{}", first_part, file_size)
-    })
+// Number of bytes hashed from the start of each file before committing to a full hash
+const PARTIAL_HASH_SIZE: u64 = 4096;
+const COMPARE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Hash the first `PARTIAL_HASH_SIZE` bytes of a file
+fn partial_hash(path: &Path) -> Result<blake3::Hash> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open file for hashing: {:?}", path))?;
+    let mut hasher = blake3::Hasher::new();
+    hasher
+        .update_reader(file.take(PARTIAL_HASH_SIZE))
+        .with_context(|| format!("Failed to hash file: {:?}", path))?;
+    Ok(hasher.finalize())
 }
 
-pub fn directory_cleaner_based_on_duplicate_files(
-    directory: &String,
-    dry_run: bool,
-    report_data: &mut ReportData,
-    paths_to_ignore: &[String],
-) -> Result<()> {
-    let mut set: HashSet<String> = HashSet::new();
-    let mut del_count: u32 = 0;
-    let mut del_size: u64 = 0;
-    // Convert paths_to_ignore to a collection of PathBuf for easier comparison
-    let ignore_set: HashSet<PathBuf> = paths_to_ignore.iter().map(PathBuf::from).collect();
-    for entry in WalkDir::new(directory).into_iter() {
-        match entry {
-            Ok(dir) => {
-                let path = dir.path();
-
-                if path.is_file() {
-                    let metadata = fs::metadata(path)
-                        .with_context(|| format!("Failed to read metadata for file: {:?}", path))?;
-
-                    // use first word in filename + filesize as they key till we think of something
-                    // better
-
-                    let file_key = get_file_key(path.file_name(), metadata.len());
-
-                    match file_key {
-                        Some(file_key) => {
-                            if set.contains(&file_key) {
-                                delete_file(path, dry_run, &ignore_set)?;
-                                del_count += 1;
-                                del_size += metadata.len();
-                            } else {
-                                set.insert(file_key);
-                            }
-                        }
-
-                        None => {}
+/// Hash the whole content of a file
+pub fn full_hash(path: &Path) -> Result<blake3::Hash> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open file for hashing: {:?}", path))?;
+    let mut hasher = blake3::Hasher::new();
+    hasher
+        .update_reader(file)
+        .with_context(|| format!("Failed to hash file: {:?}", path))?;
+    Ok(hasher.finalize())
+}
+
+/// Compare two files byte for byte
+fn files_are_identical(left: &Path, right: &Path) -> Result<bool> {
+    let mut left_reader = BufReader::new(
+        File::open(left).with_context(|| format!("Failed to open file: {:?}", left))?,
+    );
+    let mut right_reader = BufReader::new(
+        File::open(right).with_context(|| format!("Failed to open file: {:?}", right))?,
+    );
+    let mut left_buf = vec![0u8; COMPARE_BUFFER_SIZE];
+    let mut right_buf = vec![0u8; COMPARE_BUFFER_SIZE];
+
+    loop {
+        let read = left_reader.read(&mut left_buf)?;
+        if read == 0 {
+            // left is exhausted, right must be too
+            return Ok(right_reader.read(&mut right_buf[..1])? == 0);
+        }
+        if right_reader.read_exact(&mut right_buf[..read]).is_err() {
+            return Ok(false);
+        }
+        if left_buf[..read] != right_buf[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Split every group into smaller groups of files sharing the same key, dropping files whose
+/// key can't be computed and groups left with a single member
+fn refine_groups<K, F>(
+    groups: Vec<Vec<usize>>,
+    records: &[FileRecord],
+    key_fn: F,
+) -> Vec<Vec<usize>>
+where
+    K: std::hash::Hash + Eq + Clone,
+    F: Fn(&Path) -> Result<K>,
+{
+    let mut refined = vec![];
+    for group in groups {
+        let mut buckets: HashMap<K, Vec<usize>> = HashMap::new();
+        // keep buckets in walk order so results don't depend on hash map iteration
+        let mut order: Vec<K> = vec![];
+        for idx in group {
+            match key_fn(&records[idx].path) {
+                Ok(key) => match buckets.get_mut(&key) {
+                    Some(bucket) => bucket.push(idx),
+                    None => {
+                        buckets.insert(key.clone(), vec![idx]);
+                        order.push(key);
                     }
-                    collect_metrics(report_data, metadata, &path, (del_count, del_size));
-                    del_count = 0;
-                    del_size = 0;
-                } else {
-                    eprintln!("File does not exist, {}", path.display())
+                },
+                Err(err) => {
+                    eprintln!("Skipping file from duplicate check, {}", err);
+                }
+            }
+        }
+        for key in order {
+            if let Some(bucket) = buckets.remove(&key) {
+                if bucket.len() > 1 {
+                    refined.push(bucket);
                 }
             }
+        }
+    }
+    refined
+}
+
+/// Find groups of files with identical content among the candidates. Files are first grouped by
+/// size, then by a hash of their first block and finally by a hash of their whole content, so
+/// only files that can still be duplicates are ever read in full. Each returned group is in walk
+/// order.
+pub fn find_duplicate_groups(records: &[FileRecord], candidates: &[usize]) -> Vec<Vec<usize>> {
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for &idx in candidates {
+        let size = records[idx].metadata.len();
+        // empty files have no content to compare and free no space
+        if size > 0 {
+            by_size.entry(size).or_default().push(idx);
+        }
+    }
+    let mut groups: Vec<Vec<usize>> = by_size.into_values().filter(|g| g.len() > 1).collect();
+    groups.sort_by_key(|g| g[0]);
+
+    let groups = refine_groups(groups, records, partial_hash);
+    refine_groups(groups, records, full_hash)
+}
+
+/// Decides which file of a duplicate group is kept, every other copy is removed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum KeepPolicy {
+    #[default]
+    Oldest,
+    Newest,
+    ShortestPath,
+    LongestPath,
+    Alphabetical,
+    /// Prefer a copy under this directory, falling back to the oldest copy
+    PreferDir(PathBuf),
+}
+
+impl FromStr for KeepPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "oldest" => Ok(KeepPolicy::Oldest),
+            "newest" => Ok(KeepPolicy::Newest),
+            "shortest" => Ok(KeepPolicy::ShortestPath),
+            "longest" => Ok(KeepPolicy::LongestPath),
+            "alpha" => Ok(KeepPolicy::Alphabetical),
+            _ => match s.strip_prefix("prefer:") {
+                Some(dir) if !dir.is_empty() => Ok(KeepPolicy::PreferDir(PathBuf::from(dir))),
+                _ => Err(anyhow::anyhow!(
+                    "Invalid keep policy: {}, expected one of oldest, newest, shortest, longest, alpha or prefer:<DIR>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl KeepPolicy {
+    /// Resolve a preferred directory against the cleaned directory, so it is compared with the
+    /// walked paths component by component. Relative directories are taken from the root, and
+    /// the directory must exist under it.
+    pub fn resolve(&self, root: &Path) -> Result<KeepPolicy> {
+        let KeepPolicy::PreferDir(dir) = self else {
+            return Ok(self.clone());
+        };
+        let resolved = root
+            .join(dir)
+            .canonicalize()
+            .with_context(|| format!("Preferred directory not found: {:?}", dir))?;
+        let canonical_root = root
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve directory: {:?}", root))?;
+        let relative = resolved.strip_prefix(&canonical_root).map_err(|_| {
+            anyhow::anyhow!(
+                "Preferred directory {:?} is outside of the cleaned directory {:?}",
+                dir,
+                root
+            )
+        })?;
+        Ok(KeepPolicy::PreferDir(root.join(relative)))
+    }
+}
+
+impl fmt::Display for KeepPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeepPolicy::Oldest => f.write_str("oldest"),
+            KeepPolicy::Newest => f.write_str("newest"),
+            KeepPolicy::ShortestPath => f.write_str("shortest"),
+            KeepPolicy::LongestPath => f.write_str("longest"),
+            KeepPolicy::Alphabetical => f.write_str("alpha"),
+            KeepPolicy::PreferDir(dir) => write!(f, "prefer:{}", dir.display()),
+        }
+    }
+}
+
+/// What happens to the redundant copies of a duplicate group
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DedupAction {
+    #[default]
+    Delete,
+    /// Replace each copy with a hardlink to the keeper
+    Hardlink,
+    /// Replace each copy with a copy-on-write clone of the keeper, falling back to a hardlink
+    Reflink,
+}
+
+impl FromStr for DedupAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "delete" => Ok(DedupAction::Delete),
+            "hardlink" => Ok(DedupAction::Hardlink),
+            "reflink" => Ok(DedupAction::Reflink),
+            _ => Err(anyhow::anyhow!(
+                "Invalid dedup action: {}, expected one of delete, hardlink or reflink",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DedupAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DedupAction::Delete => "delete",
+            DedupAction::Hardlink => "hardlink",
+            DedupAction::Reflink => "reflink",
+        })
+    }
+}
+
+/// How duplicates are found and handled, given as the options of [`DuplicateFilter`]
+#[derive(Debug, Clone, Default)]
+pub struct DedupOptions {
+    /// Compare every copy byte for byte with the keeper before removing it
+    pub verify: bool,
+    pub keep: KeepPolicy,
+    pub action: DedupAction,
+}
 
+impl DedupOptions {
+    /// Read the options given to [`DuplicateFilter`], resolving a preferred directory against
+    /// the cleaned directory
+    pub fn from_context(context: &FilterContext) -> Result<Self> {
+        let value = |id: &str| context.option(id).and_then(|values| values.first());
+        Ok(DedupOptions {
+            verify: match context.option("verify_duplicates") {
+                Some(values) => flag_enabled(values)?,
+                None => false,
+            },
+            keep: match value("keep") {
+                Some(keep) => keep.parse::<KeepPolicy>()?.resolve(&context.root)?,
+                None => KeepPolicy::default(),
+            },
+            action: value("dedup_action")
+                .map(|action| action.parse())
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The options as they're given on the command line, for [`FilterContext::options`]
+    pub fn to_options(&self) -> HashMap<String, Vec<String>> {
+        HashMap::from([
+            (
+                "verify_duplicates".to_string(),
+                vec![self.verify.to_string()],
+            ),
+            ("keep".to_string(), vec![self.keep.to_string()]),
+            ("dedup_action".to_string(), vec![self.action.to_string()]),
+        ])
+    }
+}
+
+/// Temporary sibling used to build the link before it atomically replaces the copy
+fn link_temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.dir-cleaner-{}", file_name, std::process::id()))
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, target: &Path) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let src = File::open(source).with_context(|| format!("Failed to open file: {:?}", source))?;
+    let dst =
+        File::create(target).with_context(|| format!("Failed to create file: {:?}", target))?;
+    // SAFETY: both descriptors are owned by the files above and stay open for the call
+    let ret = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) };
+    if ret != 0 {
+        let err = std::io::Error::last_os_error();
+        drop(dst);
+        let _ = fs::remove_file(target);
+        return Err(err).with_context(|| format!("Failed to reflink {:?}", source));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(source: &Path, _target: &Path) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "Reflinks are not supported on this platform: {:?}",
+        source
+    ))
+}
+
+/// Replace `duplicate` with a link to `keeper`, returning the kind of link that was made. The
+/// link is created next to the duplicate and renamed over it, so the duplicate is never missing.
+fn replace_with_link(keeper: &Path, duplicate: &Path, action: DedupAction) -> Result<DedupAction> {
+    let temp = link_temp_path(duplicate);
+
+    if action == DedupAction::Reflink {
+        match reflink(keeper, &temp) {
+            Ok(()) => {
+                // the clone is a new inode, so carry over the copy's own permissions and times
+                let result = fs::metadata(duplicate)
+                    .with_context(|| format!("Failed to read metadata: {:?}", duplicate))
+                    .and_then(|metadata| {
+                        fs::set_permissions(&temp, metadata.permissions())?;
+                        filetime::set_file_mtime(
+                            &temp,
+                            filetime::FileTime::from_last_modification_time(&metadata),
+                        )?;
+                        fs::rename(&temp, duplicate)
+                            .with_context(|| format!("Failed to replace file: {:?}", duplicate))
+                    });
+                if result.is_err() {
+                    let _ = fs::remove_file(&temp);
+                }
+                return result.map(|_| DedupAction::Reflink);
+            }
             Err(err) => {
+                eprintln!("{:#}, falling back to a hardlink", err);
+            }
+        }
+    }
+
+    fs::hard_link(keeper, &temp)
+        .with_context(|| format!("Failed to hardlink {:?} to {:?}", keeper, duplicate))?;
+    if let Err(err) = fs::rename(&temp, duplicate) {
+        let _ = fs::remove_file(&temp);
+        return Err(err).with_context(|| format!("Failed to replace file: {:?}", duplicate));
+    }
+    Ok(DedupAction::Hardlink)
+}
+
+/// Replace a linked duplicate with a copy of its own of the keeper's content, undoing
+/// [`replace_with_link`]. The copy is built next to the duplicate and renamed over it.
+pub fn replace_with_copy(keeper: &Path, duplicate: &Path) -> Result<()> {
+    let temp = link_temp_path(duplicate);
+    let result = fs::copy(keeper, &temp)
+        .with_context(|| format!("Failed to copy {:?}", keeper))
+        .and_then(|_| {
+            if let Ok(metadata) = fs::metadata(duplicate) {
+                fs::set_permissions(&temp, metadata.permissions())?;
+                filetime::set_file_mtime(
+                    &temp,
+                    filetime::FileTime::from_last_modification_time(&metadata),
+                )?;
+            }
+            fs::rename(&temp, duplicate)
+                .with_context(|| format!("Failed to replace file: {:?}", duplicate))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn same_inode(left: &fs::Metadata, right: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    left.dev() == right.dev() && left.ino() == right.ino()
+}
+
+#[cfg(not(unix))]
+fn same_inode(_left: &fs::Metadata, _right: &fs::Metadata) -> bool {
+    false
+}
+
+/// Pick the file to keep from a duplicate group. Ties are broken by path so the result never
+/// depends on the order the files were walked in.
+pub fn select_keeper(policy: &KeepPolicy, group: &[usize], records: &[FileRecord]) -> usize {
+    let modified = |idx: usize| records[idx].metadata.modified().ok();
+    let path_len = |idx: usize| records[idx].path.as_os_str().len();
+
+    let mut candidates: Vec<usize> = group.to_vec();
+    candidates.sort_by(|a, b| records[*a].path.cmp(&records[*b].path));
+
+    let oldest = |candidates: &[usize]| {
+        *candidates
+            .iter()
+            .min_by_key(|idx| modified(**idx))
+            .expect("duplicate groups are never empty")
+    };
+
+    match policy {
+        KeepPolicy::Oldest => oldest(&candidates),
+        KeepPolicy::Newest => *candidates
+            .iter()
+            .rev()
+            .max_by_key(|idx| modified(**idx))
+            .expect("duplicate groups are never empty"),
+        KeepPolicy::ShortestPath => *candidates
+            .iter()
+            .min_by_key(|idx| path_len(**idx))
+            .expect("duplicate groups are never empty"),
+        KeepPolicy::LongestPath => *candidates
+            .iter()
+            .rev()
+            .max_by_key(|idx| path_len(**idx))
+            .expect("duplicate groups are never empty"),
+        KeepPolicy::Alphabetical => candidates[0],
+        KeepPolicy::PreferDir(dir) => {
+            let preferred: Vec<usize> = candidates
+                .iter()
+                .copied()
+                .filter(|idx| records[*idx].path.starts_with(dir))
+                .collect();
+            if preferred.is_empty() {
+                oldest(&candidates)
+            } else {
+                oldest(&preferred)
+            }
+        }
+    }
+}
+
+/// A duplicate group once its keeper has been chosen
+#[derive(Debug)]
+pub struct DuplicateSet {
+    pub keeper: usize,
+    /// Redundant copies of the keeper, ready to be removed or linked
+    pub copies: Vec<usize>,
+    /// Whether the copies are removed by the run's action or linked to the keeper
+    pub action: DedupAction,
+}
+
+/// Find the duplicates among the eligible records and pick the keeper of each group
+pub fn find_duplicates(
+    records: &[FileRecord],
+    eligible: &[bool],
+    options: &DedupOptions,
+) -> Result<Vec<DuplicateSet>> {
+    // a symlink has its target's content without being a copy of it, removing either one
+    // would leave the other without the content
+    let candidates: Vec<usize> = (0..records.len())
+        .filter(|idx| eligible[*idx] && !records[*idx].metadata.file_type().is_symlink())
+        .collect();
+    let mut sets = vec![];
+
+    for group in find_duplicate_groups(records, &candidates) {
+        let keeper = select_keeper(&options.keep, &group, records);
+        let mut copies = vec![];
+        for &idx in group.iter().filter(|idx| **idx != keeper) {
+            if options.verify && !files_are_identical(&records[keeper].path, &records[idx].path)? {
                 eprintln!(
-                    "Encountered error trying to get file. operation proceeding..., {}",
-                    err
+                    "Hash matched but content differs, keeping file: {}",
+                    records[idx].path.display()
                 );
+                continue;
             }
+            if same_inode(&records[keeper].metadata, &records[idx].metadata) {
+                // already a hardlink of the keeper, removing it would free nothing
+                continue;
+            }
+            copies.push(idx);
+        }
+        if !copies.is_empty() {
+            sets.push(DuplicateSet {
+                keeper,
+                copies,
+                action: options.action,
+            });
         }
     }
 
-    Ok(())
+    Ok(sets)
+}
+
+/// Replace a duplicate with a link to its keeper, returning the kind of link it was (or in a
+/// dry run, could have been) replaced with. Copies that can't be linked are left untouched.
+pub fn link_duplicate(
+    keeper: &Path,
+    duplicate: &Path,
+    action: DedupAction,
+    dry_run: bool,
+) -> Option<DedupAction> {
+    if dry_run {
+        eprintln!(
+            "\n {} could have been linked to {}",
+            duplicate.display().to_string().bold().yellow(),
+            keeper.display()
+        );
+        return Some(action);
+    }
+    match replace_with_link(keeper, duplicate, action) {
+        Ok(kind) => {
+            eprintln!("Replaced {:?} with a {:?}", duplicate, kind);
+            Some(kind)
+        }
+        Err(err) => {
+            eprintln!("Could not link duplicate, keeping file: {:#}", err);
+            None
+        }
+    }
+}
+
+/// `-r true` cleans redundant copies of identical files
+pub struct DuplicateFilter;
+
+impl Filter for DuplicateFilter {
+    fn name(&self) -> &'static str {
+        "remove_duplicates"
+    }
+
+    fn description(&self) -> &'static str {
+        "duplicate files"
+    }
+
+    fn arg(&self) -> Arg {
+        Arg::new(self.name())
+            .short('r')
+            .long("dedup")
+            .required(false)
+            .value_parser(clap::value_parser!(bool))
+            .help("Remove Duplicate")
+    }
+
+    fn is_flag(&self) -> bool {
+        true
+    }
+
+    fn parse(&self, values: &[String], context: &FilterContext) -> Result<Option<Expr>> {
+        if !flag_enabled(values)? {
+            return Ok(None);
+        }
+        // a bad option is reported before the walk
+        DedupOptions::from_context(context)?;
+        Ok(Some(Expr::Duplicate))
+    }
+
+    fn options(&self) -> Vec<Arg> {
+        vec![
+            Arg::new("verify_duplicates")
+                .long("verify")
+                .required(false)
+                .value_parser(clap::value_parser!(bool))
+                .help("Compare duplicates byte for byte before removing them"),
+            Arg::new("keep")
+                .long("keep")
+                .required(false)
+                .value_parser(|s: &str| s.parse::<KeepPolicy>().map_err(|e| e.to_string()))
+                .help("Copy to keep from each duplicate group: oldest, newest, shortest, longest, alpha or prefer:<DIR>"),
+            Arg::new("dedup_action")
+                .long("dedup-action")
+                .required(false)
+                .value_parser(|s: &str| s.parse::<DedupAction>().map_err(|e| e.to_string()))
+                .help("What to do with duplicate copies: delete, hardlink or reflink"),
+        ]
+    }
+
+    fn print_report(&self, report: &Report) {
+        // Duplicate groups with the copy that was kept (if any)
+        if !report.duplicate_groups.is_empty() {
+            println!("\n{}", "Duplicate Groups".bold().magenta());
+            for group in &report.duplicate_groups {
+                println!("{} {}", "kept".bold().green(), group.keeper.green());
+                for path in &group.removed {
+                    println!("  {} {}", "removed".bold().red(), path.red());
+                }
+                for path in &group.linked {
+                    println!("  {} {}", "linked".bold().blue(), path.blue());
+                }
+            }
+        }
+    }
+}
+
+/// Group the eligible files by content and pick the keeper of each group, when the expression
+/// uses `dup()`. Runs once after the walk, before any file is matched: the copies of the sets
+/// returned are what `dup()` matches.
+pub fn find_duplicate_sets(
+    expr: &Expr,
+    records: &[FileRecord],
+    eligible: &[bool],
+    context: &FilterContext,
+) -> Result<Vec<DuplicateSet>> {
+    if !expr.uses_duplicates() {
+        return Ok(vec![]);
+    }
+    let options = DedupOptions::from_context(context)?;
+    let mut sets = find_duplicates(records, eligible, &options)?;
+    // a copy is only redundant while its keeper stays around
+    sets.retain(|set| !expr.eval(&records[set.keeper], false));
+    Ok(sets)
+}
+
+pub fn directory_cleaner_based_on_duplicate_files(
+    directory: &String,
+    dry_run: bool,
+    options: &DedupOptions,
+    report_data: &mut Report,
+    paths_to_ignore: &[String],
+) -> Result<()> {
+    let filters = Filters {
+        expr: Some(Expr::Duplicate),
+        context: FilterContext {
+            options: options.to_options(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    scan_directory(
+        directory,
+        &filters,
+        &Action::Delete,
+        dry_run,
+        report_data,
+        paths_to_ignore,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn record(path: &Path) -> Result<FileRecord> {
+        Ok(FileRecord {
+            path: path.to_path_buf(),
+            relative_path: PathBuf::from(path.file_name().unwrap()),
+            metadata: fs::metadata(path)?,
+            git_ignored: false,
+        })
+    }
+
+    #[test]
+    fn test_files_with_same_content_but_different_names_are_duplicates() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let original = temp_dir.path().join("original.bin");
+        let copy = temp_dir.path().join("completely different name.bin");
+        fs::write(&original, "same content")?;
+        fs::write(&copy, "same content")?;
+
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        let options = DedupOptions {
+            verify: true,
+            ..Default::default()
+        };
+        directory_cleaner_based_on_duplicate_files(&dir_str, false, &options, &mut report, &[])?;
+
+        assert!(
+            original.exists() != copy.exists(),
+            "Exactly one copy should be left"
+        );
+        assert_eq!(report.files_deleted, 1);
+        assert_eq!(report.total_file_size_deleted, 12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_with_same_prefix_and_size_but_different_content_are_kept() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let report_1 = temp_dir.path().join("report 1.pdf");
+        let report_2 = temp_dir.path().join("report 2.pdf");
+        fs::write(&report_1, "quarterly numbers: 1")?;
+        fs::write(&report_2, "quarterly numbers: 2")?;
+
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        directory_cleaner_based_on_duplicate_files(
+            &dir_str,
+            false,
+            &DedupOptions::default(),
+            &mut report,
+            &[],
+        )?;
+
+        assert!(report_1.exists() && report_2.exists());
+        assert_eq!(report.files_deleted, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_differing_after_the_first_block_are_kept() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let file_1 = temp_dir.path().join("a.bin");
+        let file_2 = temp_dir.path().join("b.bin");
+        let mut content = vec![7u8; PARTIAL_HASH_SIZE as usize * 2];
+        fs::write(&file_1, &content)?;
+        content[PARTIAL_HASH_SIZE as usize + 1] = 8;
+        fs::write(&file_2, &content)?;
+
+        let records = vec![record(&file_1)?, record(&file_2)?];
+        assert!(find_duplicate_groups(&records, &[0, 1]).is_empty());
+        assert!(!files_are_identical(&file_1, &file_2)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_policy_picks_keeper_independent_of_walk_order() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let nested = temp_dir.path().join("archive/2023");
+        fs::create_dir_all(&nested)?;
+        let short = temp_dir.path().join("b.txt");
+        let long = nested.join("a.txt");
+        fs::write(&short, "duplicate")?;
+        fs::write(&long, "duplicate")?;
+        let old = filetime::FileTime::from_unix_time(1_000_000, 0);
+        filetime::set_file_mtime(&long, old)?;
+
+        let records = vec![record(&short)?, record(&long)?];
+        let keeper = |policy: KeepPolicy| select_keeper(&policy, &[0, 1], &records);
+
+        assert_eq!(keeper(KeepPolicy::Oldest), 1);
+        assert_eq!(keeper(KeepPolicy::Newest), 0);
+        assert_eq!(keeper(KeepPolicy::ShortestPath), 0);
+        assert_eq!(keeper(KeepPolicy::LongestPath), 1);
+        assert_eq!(keeper(KeepPolicy::Alphabetical), 1);
+        assert_eq!(
+            keeper(KeepPolicy::PreferDir(temp_dir.path().join("archive"))),
+            1
+        );
+        assert_eq!(keeper("prefer:/nowhere".parse()?), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preferred_directory_is_resolved_against_the_root() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path().join("data");
+        fs::create_dir_all(root.join("archive"))?;
+        fs::create_dir_all(temp_dir.path().join("elsewhere"))?;
+        let prefer = |dir: PathBuf| KeepPolicy::PreferDir(dir).resolve(&root);
+
+        let archive = KeepPolicy::PreferDir(root.join("archive"));
+        assert_eq!(prefer(PathBuf::from("archive"))?, archive);
+        assert_eq!(prefer(PathBuf::from("./archive/"))?, archive);
+        assert_eq!(prefer(root.join("archive"))?, archive);
+        assert_eq!(prefer(root.join("../data/archive"))?, archive);
+        assert!(prefer(temp_dir.path().join("elsewhere")).is_err());
+        assert!(prefer(PathBuf::from("missing")).is_err());
+        assert_eq!(KeepPolicy::Newest.resolve(&root)?, KeepPolicy::Newest);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup_options_are_read_from_the_filter_options() -> Result<()> {
+        let temp_dir = tempdir()?;
+        fs::create_dir(temp_dir.path().join("archive"))?;
+        let options = DedupOptions {
+            verify: true,
+            keep: KeepPolicy::PreferDir(temp_dir.path().join("archive")),
+            action: DedupAction::Hardlink,
+        };
+        let context = FilterContext {
+            root: temp_dir.path().to_path_buf(),
+            options: options.to_options(),
+            ..Default::default()
+        };
+        let read = DedupOptions::from_context(&context)?;
+        assert!(read.verify);
+        assert_eq!(read.keep, options.keep);
+        assert_eq!(read.action, DedupAction::Hardlink);
+
+        let defaults = DedupOptions::from_context(&FilterContext::default())?;
+        assert!(!defaults.verify);
+        assert_eq!(defaults.keep, KeepPolicy::Oldest);
+        assert_eq!(defaults.action, DedupAction::Delete);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_groups_are_reported_with_keeper() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let keep = temp_dir.path().join("a.txt");
+        let copy = temp_dir.path().join("z.txt");
+        fs::write(&keep, "duplicate")?;
+        fs::write(&copy, "duplicate")?;
+
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        let options = DedupOptions {
+            keep: KeepPolicy::Alphabetical,
+            ..Default::default()
+        };
+        directory_cleaner_based_on_duplicate_files(&dir_str, false, &options, &mut report, &[])?;
+
+        assert!(keep.exists() && !copy.exists());
+        assert_eq!(report.duplicate_groups.len(), 1);
+        assert_eq!(
+            report.duplicate_groups[0].keeper,
+            keep.display().to_string()
+        );
+        assert_eq!(
+            report.duplicate_groups[0].removed,
+            vec![copy.display().to_string()]
+        );
+        assert!("newest-first".parse::<KeepPolicy>().is_err());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlink_action_replaces_copies_with_links() -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = tempdir()?;
+        let keep = temp_dir.path().join("a.txt");
+        let copy = temp_dir.path().join("b.txt");
+        fs::write(&keep, "duplicate")?;
+        fs::write(&copy, "duplicate")?;
+
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        let options = DedupOptions {
+            keep: KeepPolicy::Alphabetical,
+            action: DedupAction::Hardlink,
+            ..Default::default()
+        };
+        directory_cleaner_based_on_duplicate_files(&dir_str, false, &options, &mut report, &[])?;
+
+        assert!(keep.exists() && copy.exists());
+        assert_eq!(fs::metadata(&keep)?.ino(), fs::metadata(&copy)?.ino());
+        assert_eq!(fs::read_to_string(&copy)?, "duplicate");
+        assert_eq!(report.files_deleted, 0);
+        assert_eq!(report.total_file_size_deleted, 0);
+        assert_eq!(report.files_linked, 1);
+        assert_eq!(report.total_file_size_linked, 9);
+
+        // running again finds nothing left to reclaim
+        let mut report = Report::new();
+        directory_cleaner_based_on_duplicate_files(&dir_str, false, &options, &mut report, &[])?;
+        assert_eq!(report.files_linked, 0);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_are_never_duplicates_of_their_target() -> Result<()> {
+        for (target, link, keep) in [
+            ("data/report.txt", "alias.txt", KeepPolicy::Oldest),
+            ("z.txt", "a.txt", KeepPolicy::Alphabetical),
+        ] {
+            let temp_dir = tempdir()?;
+            let target = temp_dir.path().join(target);
+            let link = temp_dir.path().join(link);
+            fs::create_dir_all(target.parent().unwrap())?;
+            fs::write(&target, "only copy")?;
+            std::os::unix::fs::symlink(&target, &link)?;
+
+            let mut report = Report::new();
+            let dir_str = temp_dir.path().to_str().unwrap().to_string();
+            let options = DedupOptions {
+                keep,
+                ..Default::default()
+            };
+            directory_cleaner_based_on_duplicate_files(
+                &dir_str,
+                false,
+                &options,
+                &mut report,
+                &[],
+            )?;
+
+            assert_eq!(fs::read_to_string(&link)?, "only copy");
+            assert_eq!(report.files_deleted, 0);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlinks_of_the_keeper_are_not_removed() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let keep = temp_dir.path().join("a.txt");
+        let link = temp_dir.path().join("b.txt");
+        fs::write(&keep, "duplicate")?;
+        fs::hard_link(&keep, &link)?;
+
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        directory_cleaner_based_on_duplicate_files(
+            &dir_str,
+            false,
+            &DedupOptions::default(),
+            &mut report,
+            &[],
+        )?;
+
+        assert!(keep.exists() && link.exists());
+        assert_eq!(report.total_file_size_deleted, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reflink_action_keeps_content_when_falling_back() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let keep = temp_dir.path().join("a.txt");
+        let copy = temp_dir.path().join("b.txt");
+        fs::write(&keep, "duplicate")?;
+        fs::write(&copy, "duplicate")?;
+
+        let kind = replace_with_link(&keep, &copy, DedupAction::Reflink)?;
+
+        assert_ne!(kind, DedupAction::Delete);
+        // a clone is an inode of its own, the fallback a hardlink to the keeper
+        #[cfg(unix)]
+        assert_eq!(
+            same_inode(&fs::metadata(&keep)?, &fs::metadata(&copy)?),
+            kind == DedupAction::Hardlink
+        );
+        assert_eq!(fs::read_to_string(&copy)?, "duplicate");
+        assert!(!link_temp_path(&copy).exists());
+
+        Ok(())
+    }
 }
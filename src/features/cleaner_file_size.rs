@@ -1,54 +1,109 @@
-use anyhow::{Context, Result};
-use std::collections::HashSet;
-use std::fs;
-use std::path::PathBuf;
-use walkdir::WalkDir;
+use anyhow::Result;
+use clap::Arg;
 
-use crate::ReportData;
+use crate::report::Report;
 
-use super::utils::{collect_metrics, delete_file};
+use super::action::Action;
+use super::filter::{Filter, FilterContext};
+use super::filter_expression::{Expr, SizeOp};
+use super::scanner::{scan_directory, FileRecord, Filters};
+use super::utils::{parse_size, parse_size_range};
+
+pub fn matches_min_size(record: &FileRecord, size: u64) -> bool {
+    record.metadata.len() >= size
+}
+
+/// `-s 10M` cleans files of at least 10M, `-s 10M..2G` files within the range
+pub struct MinSizeFilter;
+
+impl Filter for MinSizeFilter {
+    fn name(&self) -> &'static str {
+        "size"
+    }
+
+    fn description(&self) -> &'static str {
+        "size"
+    }
+
+    fn arg(&self) -> Arg {
+        Arg::new(self.name())
+            .short('s')
+            .long("size")
+            .required(false)
+            .value_parser(|s: &str| {
+                parse_size_range(s)
+                    .map(|_| s.to_string())
+                    .map_err(|e| e.to_string())
+            })
+            .help(
+                "Minimum size to clear, or a range such as 10M..2G (units: K, M, G, KiB, MB, ...)",
+            )
+    }
+
+    fn parse(&self, values: &[String], _context: &FilterContext) -> Result<Option<Expr>> {
+        let Some(value) = values.first() else {
+            return Ok(None);
+        };
+        Ok(match parse_size_range(value)? {
+            (Some(min), Some(max)) => Some(
+                Expr::Size(SizeOp::GreaterOrEqual, min).and(Expr::Size(SizeOp::LessOrEqual, max)),
+            ),
+            (Some(min), None) => Some(Expr::Size(SizeOp::GreaterOrEqual, min)),
+            (None, Some(max)) => Some(Expr::Size(SizeOp::LessOrEqual, max)),
+            (None, None) => None,
+        })
+    }
+}
+
+/// `--max-size 2G` cleans files of at most 2G
+pub struct MaxSizeFilter;
+
+impl Filter for MaxSizeFilter {
+    fn name(&self) -> &'static str {
+        "max_size"
+    }
+
+    fn description(&self) -> &'static str {
+        "max size"
+    }
+
+    fn arg(&self) -> Arg {
+        Arg::new(self.name())
+            .long("max-size")
+            .required(false)
+            .value_parser(|s: &str| {
+                parse_size(s)
+                    .map(|_| s.to_string())
+                    .map_err(|e| e.to_string())
+            })
+            .help("Maximum size to clear (units: K, M, G, KiB, MB, ...)")
+    }
+
+    fn parse(&self, values: &[String], _context: &FilterContext) -> Result<Option<Expr>> {
+        values
+            .first()
+            .map(|value| Ok(Expr::Size(SizeOp::LessOrEqual, parse_size(value)?)))
+            .transpose()
+    }
+}
 
 pub fn directory_cleaner_based_on_file_size(
     directory: &String,
     size: u64,
     dry_run: bool,
-    report_data: &mut ReportData,
+    report_data: &mut Report,
     paths_to_ignore: &[String],
 ) -> Result<()> {
-    let mut del_count: u32 = 0;
-    let mut del_size: u64 = 0;
-    // Convert paths_to_ignore to a collection of PathBuf for easier comparison
-    let ignore_set: HashSet<PathBuf> = paths_to_ignore.iter().map(PathBuf::from).collect();
-    for entry in WalkDir::new(directory).into_iter() {
-        match entry {
-            Ok(dir) => {
-                let path = dir.path();
-
-                if path.is_file() {
-                    let metadata = fs::metadata(path)
-                        .with_context(|| format!("Failed to read metadata for file: {:?}", path))?;
-
-                    if metadata.len() >= size {
-                        delete_file(path, dry_run, &ignore_set)?;
-                        del_count += 1;
-                        del_size += metadata.len();
-                    }
-                    collect_metrics(report_data, metadata, &path, (del_count, del_size));
-                    del_count = 0;
-                    del_size = 0;
-                } else {
-                    eprintln!("File does not exist, {}", path.display())
-                }
-            }
-
-            Err(err) => {
-                eprintln!(
-                    "Encountered error trying to get file. operation proceeding..., {}",
-                    err
-                );
-            }
-        }
-    }
-
-    Ok(())
+    let filters = Filters {
+        expr: Some(Expr::Size(SizeOp::GreaterOrEqual, size)),
+        ..Default::default()
+    };
+    scan_directory(
+        directory,
+        &filters,
+        &Action::Delete,
+        dry_run,
+        report_data,
+        paths_to_ignore,
+    )
 }
@@ -1,71 +1,67 @@
-use anyhow::{Context, Result};
-use std::collections::HashSet;
-use std::fs;
-use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use anyhow::Result;
+use clap::Arg;
+
+use crate::report::Report;
+
+use super::action::Action;
+use super::filter::{Filter, FilterContext};
+use super::filter_expression::Expr;
+use super::scanner::{scan_directory, FileRecord, Filters};
+
+pub fn matches_type(record: &FileRecord, types_to_clear: &[String]) -> bool {
+    let ext = record
+        .path
+        .extension()
+        .and_then(|ex| ex.to_str())
+        .unwrap_or("");
+    types_to_clear.iter().any(|p| ext == p)
+}
+
+/// `-t log tmp` cleans files with one of these extensions
+pub struct TypeFilter;
+
+impl Filter for TypeFilter {
+    fn name(&self) -> &'static str {
+        "types"
+    }
 
-use crate::features::utils::delete_file;
-use crate::ReportData;
+    fn description(&self) -> &'static str {
+        "file type"
+    }
 
-use super::utils::collect_metrics;
+    fn arg(&self) -> Arg {
+        Arg::new(self.name())
+            .short('t')
+            .long("types")
+            .required(false)
+            .num_args(1..) // Allow multiple values
+            .help("Types to clean (space-separated)")
+    }
+
+    fn parse(&self, values: &[String], _context: &FilterContext) -> Result<Option<Expr>> {
+        Ok((!values.is_empty()).then(|| Expr::Ext(values.to_vec())))
+    }
+}
 
 pub fn directory_cleaner_based_on_file_type(
     dir: &String,
     types_to_clear: &[String],
     dry_run: bool,
-    report_data: &mut ReportData,
+    report_data: &mut Report,
     paths_to_ignore: &[String],
 ) -> Result<()> {
-    let mut del_count: u32 = 0;
-    let mut del_size: u64 = 0;
-
-    // Convert paths_to_ignore to a collection of PathBuf for easier comparison
-    let ignore_set: HashSet<PathBuf> = paths_to_ignore.iter().map(PathBuf::from).collect();
-    for entry in WalkDir::new(dir).into_iter().filter_map(|f| {
-        match f {
-            Ok(entry) => Some(entry), // Return valid entries
-            Err(err) => {
-                eprintln!(
-                    "Error accessing file: {}, Error: {}",
-                    err.path().unwrap_or_else(|| Path::new("unknown")).display(),
-                    err
-                );
-                None // Skip erroneous entries
-            }
-        }
-    }) {
-        let path = entry.path();
-
-        if ignore_set
-            .iter()
-            .any(|ignore_path| path.starts_with(ignore_path))
-        {
-            println!("Skipping ignored path: {:?}", path);
-            continue; // Skip this path if it's in the ignore list
-        }
-
-        if path.is_file() {
-            let ext = path.extension().and_then(|ex| ex.to_str()).unwrap_or("");
-
-            let metadata = fs::metadata(path)
-                .with_context(|| format!("Failed to read metadata for file: {:?}", path))?;
-
-            if types_to_clear.iter().any(|p| ext == p) {
-                println!("Deleting file: {:?}", path);
-
-                delete_file(path, dry_run, &ignore_set)?;
-                del_count += 1;
-                del_size += metadata.len();
-            }
-            collect_metrics(report_data, metadata, &path, (del_count, del_size));
-            del_count = 0;
-            del_size = 0;
-        } else {
-            eprintln!("File does not exist, {}", path.display())
-        }
-    }
-
-    Ok(())
+    let filters = Filters {
+        expr: Some(Expr::Ext(types_to_clear.to_vec())),
+        ..Default::default()
+    };
+    scan_directory(
+        dir,
+        &filters,
+        &Action::Delete,
+        dry_run,
+        report_data,
+        paths_to_ignore,
+    )
 }
 
 #[cfg(test)]
@@ -99,7 +95,7 @@ mod tests {
         let dir_to_clean = files_dir.to_str().unwrap().to_string();
         let types_to_clear = vec!["txt".to_string()]; // Clearing .txt files
         let dry_run = false;
-        let mut report_data = ReportData::new();
+        let mut report_data = Report::new();
         let files_to_ignore = vec!["text1.txt".to_string()]; // Ignore specific file
 
         // Run the cleaner function
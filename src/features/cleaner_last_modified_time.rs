@@ -1,57 +1,209 @@
-use crate::{features::utils, ReportData};
-use anyhow::{Context, Result};
+use crate::features::utils;
+use crate::report::Report;
+use anyhow::Result;
 use chrono::{DateTime, Utc};
-use std::collections::HashSet;
-use std::fs;
-use std::path::PathBuf;
-use walkdir::WalkDir;
+use clap::Arg;
+use std::fmt;
+use std::str::FromStr;
 
-use super::utils::{collect_metrics, delete_file};
+use super::action::Action;
+use super::filter::{Filter, FilterContext};
+use super::filter_expression::Expr;
+use super::scanner::{scan_directory, FileRecord, Filters};
+
+/// Which timestamp of a file the age filters compare against
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TimeField {
+    /// Last modification time
+    #[default]
+    Modified,
+    /// Last access time
+    Accessed,
+    /// Last status change time
+    Changed,
+    /// Creation time, where the filesystem records it
+    Birth,
+}
+
+impl FromStr for TimeField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mtime" => Ok(TimeField::Modified),
+            "atime" => Ok(TimeField::Accessed),
+            "ctime" => Ok(TimeField::Changed),
+            "birth" | "btime" => Ok(TimeField::Birth),
+            _ => Err(anyhow::anyhow!(
+                "Invalid time field: {}, expected one of mtime, atime, ctime or birth",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TimeField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TimeField::Modified => "mtime",
+            TimeField::Accessed => "atime",
+            TimeField::Changed => "ctime",
+            TimeField::Birth => "birth",
+        })
+    }
+}
+
+#[cfg(unix)]
+fn changed_time(metadata: &std::fs::Metadata) -> Option<DateTime<Utc>> {
+    use std::os::unix::fs::MetadataExt;
+    DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32)
+}
+
+#[cfg(not(unix))]
+fn changed_time(_metadata: &std::fs::Metadata) -> Option<DateTime<Utc>> {
+    None
+}
+
+/// The requested timestamp of a file, if the platform and filesystem provide it
+pub fn file_time(record: &FileRecord, field: TimeField) -> Option<DateTime<Utc>> {
+    let time = match field {
+        TimeField::Modified => record.metadata.modified(),
+        TimeField::Accessed => record.metadata.accessed(),
+        // std reads the birth time through statx on Linux
+        TimeField::Birth => record.metadata.created(),
+        TimeField::Changed => return changed_time(&record.metadata),
+    };
+    time.ok().map(DateTime::<Utc>::from)
+}
+
+pub fn is_older_than(record: &FileRecord, cutoff_date: DateTime<Utc>, field: TimeField) -> bool {
+    file_time(record, field).is_some_and(|time| time < cutoff_date)
+}
+
+pub fn is_newer_than(record: &FileRecord, cutoff_date: DateTime<Utc>, field: TimeField) -> bool {
+    file_time(record, field).is_some_and(|time| time >= cutoff_date)
+}
+
+/// `-a 30d` cleans files older than the cutoff
+pub struct AgeFilter;
+
+impl Filter for AgeFilter {
+    fn name(&self) -> &'static str {
+        "age"
+    }
+
+    fn description(&self) -> &'static str {
+        "age"
+    }
+
+    fn arg(&self) -> Arg {
+        Arg::new(self.name())
+            .short('a')
+            .long("age")
+            .required(false)
+            .value_parser(clap::value_parser!(String))
+            .help("Clean files older than a cutoff: YYYY-MM-DD, an RFC 3339 timestamp or an age such as 12h, 30d, 6w, 1y")
+    }
+
+    fn parse(&self, values: &[String], context: &FilterContext) -> Result<Option<Expr>> {
+        values
+            .first()
+            .map(|value| {
+                Ok(Expr::Older(
+                    utils::parse_cutoff_date(value)?,
+                    context.time_field,
+                ))
+            })
+            .transpose()
+    }
+}
+
+/// `--newer 1d` cleans files newer than the cutoff
+pub struct NewerFilter;
+
+impl Filter for NewerFilter {
+    fn name(&self) -> &'static str {
+        "newer"
+    }
+
+    fn description(&self) -> &'static str {
+        "newer than"
+    }
+
+    fn arg(&self) -> Arg {
+        Arg::new(self.name())
+            .long("newer")
+            .required(false)
+            .value_parser(clap::value_parser!(String))
+            .help("Clean files newer than a cutoff, in the same formats as --age")
+    }
+
+    fn parse(&self, values: &[String], context: &FilterContext) -> Result<Option<Expr>> {
+        values
+            .first()
+            .map(|value| {
+                Ok(Expr::Newer(
+                    utils::parse_cutoff_date(value)?,
+                    context.time_field,
+                ))
+            })
+            .transpose()
+    }
+}
 
 pub fn directory_cleaner_based_on_age(
     directory: &String,
     date: String,
     dry_run: bool,
-    report_data: &mut ReportData,
+    report_data: &mut Report,
     paths_to_ignore: &[String],
 ) -> Result<()> {
-    let mut del_count: u32 = 0;
-    let mut del_size: u64 = 0;
-    // Convert paths_to_ignore to a collection of PathBuf for easier comparison
-    let ignore_set: HashSet<PathBuf> = paths_to_ignore.iter().map(PathBuf::from).collect();
-    for entry in WalkDir::new(directory).into_iter() {
-        match entry {
-            Ok(dir) => {
-                let path = dir.path();
-
-                if path.is_file() {
-                    let metadata = fs::metadata(path)
-                        .with_context(|| format!("Failed to read metadata for file: {:?}", path))?;
-                    if let Ok(modified_time) = metadata.modified() {
-                        let modified_time_utc: DateTime<Utc> = modified_time.into();
-                        let cutoff_date = utils::parse_cutoff_date(&date)?;
-                        if modified_time_utc < cutoff_date {
-                            delete_file(path, dry_run, &ignore_set)?;
-                            del_count += 1;
-                            del_size += metadata.len();
-                        }
-                    }
-                    collect_metrics(report_data, metadata, &path, (del_count, del_size));
-                    del_count = 0;
-                    del_size = 0;
-                } else {
-                    eprintln!("File does not exist, {}", path.display())
-                }
-            }
-
-            Err(err) => {
-                eprintln!(
-                    "Encountered error trying to get file. operation proceeding..., {}",
-                    err
-                );
-            }
-        }
-    }
+    let filters = Filters {
+        expr: Some(Expr::Older(
+            utils::parse_cutoff_date(&date)?,
+            TimeField::Modified,
+        )),
+        ..Default::default()
+    };
+    scan_directory(
+        directory,
+        &filters,
+        &Action::Delete,
+        dry_run,
+        report_data,
+        paths_to_ignore,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::{set_file_atime, FileTime};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_age_uses_the_requested_time_field() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("read_long_ago.txt");
+        fs::write(&path, "content")?;
+        set_file_atime(&path, FileTime::from_unix_time(1_000_000, 0))?;
+        let record = FileRecord {
+            path: path.clone(),
+            relative_path: PathBuf::from(path.file_name().unwrap()),
+            metadata: fs::metadata(&path)?,
+            git_ignored: false,
+        };
+        let cutoff = utils::parse_cutoff_date("1y")?;
 
-    Ok(())
+        assert!(is_older_than(&record, cutoff, TimeField::Accessed));
+        assert!(!is_older_than(&record, cutoff, TimeField::Modified));
+        assert!(is_newer_than(&record, cutoff, TimeField::Modified));
+        assert!(is_newer_than(&record, cutoff, TimeField::Changed));
+        assert_eq!("ctime".parse::<TimeField>()?, TimeField::Changed);
+        assert!("yesterday".parse::<TimeField>().is_err());
+
+        Ok(())
+    }
 }
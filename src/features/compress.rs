@@ -0,0 +1,284 @@
+use anyhow::{bail, Context, Result};
+use colored::*;
+use filetime::{set_file_mtime, FileTime};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Extensions of files that are compressed already and gain nothing from another pass
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "gz", "tgz", "zst", "tzst", "xz", "txz", "bz2", "tbz2", "lz", "lz4", "lzma", "br", "zip", "7z",
+    "rar",
+];
+
+/// A dry run compresses at most this much of a file and scales the size up to the whole file
+const ESTIMATE_SAMPLE: u64 = 1024 * 1024;
+
+/// Format files are compressed to, replacing each file with a compressed sibling
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(anyhow::anyhow!(
+                "Invalid compression: {}, expected gzip or zstd",
+                s
+            )),
+        }
+    }
+}
+
+impl Compression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// The compressed sibling of a file, `app.log` becomes `app.log.zst`
+    pub fn compressed_path(&self, path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(self.extension());
+        path.with_file_name(name)
+    }
+
+    /// Whether the file can be compressed: it isn't compressed already and its compressed
+    /// sibling doesn't exist yet
+    pub fn can_compress(&self, path: &Path) -> bool {
+        let compressed = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.as_str()));
+        if compressed {
+            eprintln!("Skipping already compressed file: {:?}", path);
+            return false;
+        }
+        if fs::symlink_metadata(self.compressed_path(path)).is_ok() {
+            eprintln!("Skipping file, its compressed copy exists: {:?}", path);
+            return false;
+        }
+        true
+    }
+
+    /// Replace the file with its compressed sibling, keeping its permissions and modification
+    /// time. Returns the sibling and its size. A dry run only estimates the size: it compresses
+    /// the first megabyte, exact for smaller files, and assumes the rest of a larger file
+    /// compresses as well.
+    pub fn compress_file(&self, path: &Path, dry_run: bool) -> Result<(PathBuf, u64)> {
+        let compressed = self.compressed_path(path);
+        let mut source =
+            File::open(path).with_context(|| format!("Failed to compress file: {:?}", path))?;
+
+        if dry_run {
+            let len = source.metadata()?.len();
+            let sample = self
+                .encode(&mut (&mut source).take(ESTIMATE_SAMPLE), CountingWriter(0))?
+                .0;
+            let size = if len <= ESTIMATE_SAMPLE {
+                sample
+            } else {
+                (sample as u128 * len as u128 / ESTIMATE_SAMPLE as u128) as u64
+            };
+            if let Some(pth) = path.to_str() {
+                eprintln!(
+                    "\n {} could have been compressed to {}",
+                    pth.bold().yellow(),
+                    compressed.display()
+                );
+            }
+            return Ok((compressed, size));
+        }
+
+        // written next to the file and renamed, so a partial sibling is never left behind
+        let temp = temp_path(&compressed);
+        let written = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .map_err(anyhow::Error::from)
+            .and_then(|target| self.encode(&mut source, target))
+            .and_then(|target| {
+                target.sync_all()?;
+                copy_attributes(path, &temp)?;
+                if fs::symlink_metadata(&compressed).is_ok() {
+                    bail!("{:?} exists", compressed);
+                }
+                fs::rename(&temp, &compressed)?;
+                Ok(())
+            });
+        if let Err(err) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(err.context(format!("Failed to compress file: {:?}", path)));
+        }
+
+        fs::remove_file(path).with_context(|| format!("Failed to delete file: {:?}", path))?;
+        let size = fs::metadata(&compressed)?.len();
+        Ok((compressed, size))
+    }
+
+    fn encode<W: Write>(&self, source: &mut impl Read, target: W) -> Result<W> {
+        Ok(match self {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(target, flate2::Compression::default());
+                io::copy(source, &mut encoder)?;
+                encoder.finish()?
+            }
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(target, 0)?;
+                io::copy(source, &mut encoder)?;
+                encoder.finish()?
+            }
+        })
+    }
+
+    /// Put a compressed file back as the original, undoing [`compress_file`](Self::compress_file).
+    /// An original that exists again is never overwritten.
+    pub fn decompress_file(&self, compressed: &Path, original: &Path) -> Result<()> {
+        if fs::symlink_metadata(original).is_ok() {
+            bail!("{:?} exists again", original);
+        }
+        let source = File::open(compressed)
+            .with_context(|| format!("Failed to decompress file: {:?}", compressed))?;
+        let temp = temp_path(original);
+        let written = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut target| {
+                match self {
+                    Compression::Gzip => {
+                        io::copy(&mut flate2::read::GzDecoder::new(source), &mut target)?
+                    }
+                    Compression::Zstd => io::copy(&mut zstd::Decoder::new(source)?, &mut target)?,
+                };
+                target.sync_all()?;
+                copy_attributes(compressed, &temp)?;
+                fs::rename(&temp, original)?;
+                Ok(())
+            });
+        if let Err(err) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(err.context(format!("Failed to decompress file: {:?}", compressed)));
+        }
+        fs::remove_file(compressed)?;
+        Ok(())
+    }
+}
+
+/// Temporary sibling a file is written to before it's renamed into place
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.dir-cleaner-{}", file_name, std::process::id()))
+}
+
+fn copy_attributes(from: &Path, to: &Path) -> Result<()> {
+    let metadata = fs::metadata(from)?;
+    fs::set_permissions(to, metadata.permissions())?;
+    set_file_mtime(to, FileTime::from_last_modification_time(&metadata))?;
+    Ok(())
+}
+
+/// Counts the bytes written to it, to measure the compressed size in a dry run
+struct CountingWriter(u64);
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_compress_and_decompress() -> Result<()> {
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let temp_dir = tempdir()?;
+            let log = temp_dir.path().join("app.log");
+            fs::write(&log, "line\n".repeat(1000))?;
+            set_file_mtime(&log, FileTime::from_unix_time(1_600_000_000, 0))?;
+
+            let (dry, dry_size) = compression.compress_file(&log, true)?;
+            assert!(!dry.exists());
+            assert!(log.exists());
+
+            let (compressed, size) = compression.compress_file(&log, false)?;
+            assert_eq!(size, dry_size);
+            assert!(size < 5000);
+            assert!(!log.exists());
+            assert_eq!(
+                compressed,
+                temp_dir
+                    .path()
+                    .join(format!("app.log.{}", compression.extension()))
+            );
+            let modified = FileTime::from_last_modification_time(&fs::metadata(&compressed)?);
+            assert_eq!(modified.unix_seconds(), 1_600_000_000);
+
+            compression.decompress_file(&compressed, &log)?;
+            assert_eq!(fs::read_to_string(&log)?, "line\n".repeat(1000));
+            assert!(!compressed.exists());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dry_run_estimates_large_files_from_a_sample() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let log = temp_dir.path().join("big.log");
+        let line = "2024-01-01 12:00:00 INFO request served\n";
+        let content = line.repeat(4 * ESTIMATE_SAMPLE as usize / line.len());
+        fs::write(&log, &content)?;
+
+        let (_, estimate) = Compression::Gzip.compress_file(&log, true)?;
+        let (_, size) = Compression::Gzip.compress_file(&log, false)?;
+        assert!(estimate < content.len() as u64 / 10);
+        assert!(estimate / 2 < size && size < estimate * 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_files_are_skipped() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let archive = temp_dir.path().join("old.log.gz");
+        let log = temp_dir.path().join("app.log");
+        let done = temp_dir.path().join("done.log");
+        for path in [&archive, &log, &done] {
+            fs::write(path, "content")?;
+        }
+        fs::write(temp_dir.path().join("done.log.zst"), "content")?;
+
+        assert!(!Compression::Zstd.can_compress(&archive));
+        assert!(Compression::Zstd.can_compress(&log));
+        assert!(!Compression::Zstd.can_compress(&done));
+
+        Ok(())
+    }
+}
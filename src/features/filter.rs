@@ -0,0 +1,147 @@
+use anyhow::{bail, Result};
+use clap::Arg;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use super::cleaner_last_modified_time::TimeField;
+use super::filter_expression::Expr;
+use super::scanner::FileRecord;
+use crate::report::Report;
+
+/// Options shared by the filters, given separately from the filter values
+#[derive(Debug, Clone, Default)]
+pub struct FilterContext {
+    /// The cleaned directory, patterns starting with it are anchored to it
+    pub root: PathBuf,
+    /// Timestamp compared by the age filters
+    pub time_field: TimeField,
+    /// Values given to the filters' options, by argument id, see [`Filter::options`]
+    pub options: HashMap<String, Vec<String>>,
+}
+
+impl FilterContext {
+    /// Values given to an option, `None` when it wasn't given
+    pub fn option(&self, id: &str) -> Option<&[String]> {
+        self.options.get(id).map(|values| values.as_slice())
+    }
+}
+
+/// A filter that can be enabled from the command line or a config file. Filters are listed in
+/// [`registry`](super::registry), from which the command line arguments, config file verbs and
+/// report sections are derived.
+pub trait Filter: Sync {
+    /// Id of the command line argument, also used to look up the filter's values
+    fn name(&self) -> &'static str;
+
+    /// What the filter cleans by, shown when it's enabled, e.g. "file type"
+    fn description(&self) -> &'static str;
+
+    /// The command line argument enabling the filter. Its long and short names are also the
+    /// config file verbs.
+    fn arg(&self) -> Arg;
+
+    /// Whether the filter is enabled without a value, such as `--dedup` in a config file
+    fn is_flag(&self) -> bool {
+        false
+    }
+
+    /// Build the predicate from the values given to the argument, `None` when the values
+    /// disable the filter
+    fn parse(&self, values: &[String], context: &FilterContext) -> Result<Option<Expr>>;
+
+    /// Print the filter's own section of the report, if it has one
+    fn print_report(&self, _report: &Report) {}
+
+    /// Further arguments tuning the filter, such as `--keep` for duplicates. Their long and
+    /// short names are config file verbs too, and their values reach the filter through
+    /// [`FilterContext::options`].
+    fn options(&self) -> Vec<Arg> {
+        vec![]
+    }
+}
+
+/// The config file verbs of a filter, derived from its command line argument
+pub fn verbs(filter: &dyn Filter) -> Vec<String> {
+    arg_verbs(&filter.arg())
+}
+
+/// The config file verbs of a command line argument, its long and short names
+pub fn arg_verbs(arg: &Arg) -> Vec<String> {
+    let mut verbs = vec![];
+    if let Some(long) = arg.get_long() {
+        verbs.push(format!("--{}", long));
+    }
+    if let Some(short) = arg.get_short() {
+        verbs.push(format!("-{}", short));
+    }
+    verbs
+}
+
+/// Whether a flag filter is enabled: given without a value in a config file, or as `true` on
+/// the command line
+pub fn flag_enabled(values: &[String]) -> Result<bool> {
+    match values.first().map(|v| v.as_str()) {
+        None | Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(value) => bail!("Expected true or false, got: {}", value),
+    }
+}
+
+/// A per-file predicate plugged into a filter expression with [`Expr::Custom`], for filters
+/// that aren't built into the expression language
+pub trait Predicate: fmt::Debug + Send + Sync {
+    fn matches(&self, record: &FileRecord) -> bool;
+
+    /// Called once with every walked file before any file is matched, for stateful predicates
+    /// that compare files with each other
+    fn prepare(&self, _records: &[FileRecord]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Predicates are compared by identity
+impl PartialEq for dyn Predicate {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::cleaner_file_type::TypeFilter;
+    use crate::features::registry;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_verbs_are_derived_from_the_argument() {
+        assert_eq!(verbs(&TypeFilter), vec!["--types", "-t"]);
+    }
+
+    #[test]
+    fn test_registered_filters_have_unique_names_and_verbs() {
+        let mut names = HashSet::new();
+        let mut all_verbs = HashSet::new();
+        for filter in registry() {
+            assert_eq!(filter.arg().get_id().as_str(), filter.name());
+            assert!(names.insert(filter.name()), "{}", filter.name());
+            let options = filter.options();
+            for verb in verbs(*filter)
+                .into_iter()
+                .chain(options.iter().flat_map(arg_verbs))
+            {
+                assert!(all_verbs.insert(verb.clone()), "{}", verb);
+            }
+        }
+    }
+
+    #[test]
+    fn test_flag_values() -> Result<()> {
+        assert!(flag_enabled(&[])?);
+        assert!(flag_enabled(&["true".to_string()])?);
+        assert!(!flag_enabled(&["false".to_string()])?);
+        assert!(flag_enabled(&["yes".to_string()]).is_err());
+        Ok(())
+    }
+}
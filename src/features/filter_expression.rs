@@ -0,0 +1,587 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::Arg;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::cleaner_file_size::matches_min_size;
+use super::cleaner_file_type::matches_type;
+use super::cleaner_last_modified_time::{is_newer_than, is_older_than, TimeField};
+use super::filter::{Filter, FilterContext, Predicate};
+use super::path_matcher::PathMatcher;
+use super::scanner::FileRecord;
+use super::utils::{parse_cutoff_date, parse_size, parse_size_range};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizeOp {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+}
+
+/// A filter expression such as `ext(log) and size>10M and older(30d)`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// File extension is one of these
+    Ext(Vec<String>),
+    Size(SizeOp, u64),
+    /// Timestamp is before this date
+    Older(DateTime<Utc>, TimeField),
+    /// Timestamp is on or after this date
+    Newer(DateTime<Utc>, TimeField),
+    /// File name matches this glob
+    Name(PathMatcher),
+    /// Path relative to the cleaned directory matches these patterns, see [`PathMatcher`]
+    Path(PathMatcher),
+    /// Redundant copy of another file
+    Duplicate,
+    /// Ignored by `.gitignore`, `.ignore` or `.git/info/exclude`
+    GitIgnored,
+    /// A predicate defined outside of the expression language
+    Custom(Arc<dyn Predicate>),
+}
+
+impl Expr {
+    pub fn and(self, other: Expr) -> Expr {
+        Expr::And(Box::new(self), Box::new(other))
+    }
+
+    /// Evaluate the expression against a file. `is_duplicate` tells whether the dedup pass
+    /// found the file to be a redundant copy.
+    pub fn eval(&self, record: &FileRecord, is_duplicate: bool) -> bool {
+        match self {
+            Expr::And(left, right) => {
+                left.eval(record, is_duplicate) && right.eval(record, is_duplicate)
+            }
+            Expr::Or(left, right) => {
+                left.eval(record, is_duplicate) || right.eval(record, is_duplicate)
+            }
+            Expr::Not(inner) => !inner.eval(record, is_duplicate),
+            Expr::Ext(types) => matches_type(record, types),
+            Expr::Size(op, size) => {
+                let len = record.metadata.len();
+                match op {
+                    SizeOp::Greater => len > *size,
+                    SizeOp::GreaterOrEqual => matches_min_size(record, *size),
+                    SizeOp::Less => len < *size,
+                    SizeOp::LessOrEqual => len <= *size,
+                    SizeOp::Equal => len == *size,
+                }
+            }
+            Expr::Older(date, field) => is_older_than(record, *date, *field),
+            Expr::Newer(date, field) => is_newer_than(record, *date, *field),
+            Expr::Name(matcher) => record
+                .path
+                .file_name()
+                .is_some_and(|name| matcher.is_match(Path::new(name), false)),
+            Expr::Path(matcher) => matcher.is_match(&record.relative_path, false),
+            Expr::Duplicate => is_duplicate,
+            Expr::GitIgnored => record.git_ignored,
+            Expr::Custom(predicate) => predicate.matches(record),
+        }
+    }
+
+    /// The predicates that decided how the expression evaluated for a file: every side of a
+    /// matching `and` or failing `or`, the first deciding side otherwise
+    pub fn explain(&self, record: &FileRecord, is_duplicate: bool) -> Vec<String> {
+        let matched = self.eval(record, is_duplicate);
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                let both = matches!(self, Expr::And(..)) == matched;
+                let deciding: Vec<&Expr> = if both {
+                    vec![left, right]
+                } else if left.eval(record, is_duplicate) == matched {
+                    vec![left]
+                } else {
+                    vec![right]
+                };
+                deciding
+                    .into_iter()
+                    .flat_map(|expr| expr.explain(record, is_duplicate))
+                    .collect()
+            }
+            _ => vec![self.to_string()],
+        }
+    }
+
+    /// Whether evaluating the expression needs the dedup pass
+    pub fn uses_duplicates(&self) -> bool {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.uses_duplicates() || right.uses_duplicates()
+            }
+            Expr::Not(inner) => inner.uses_duplicates(),
+            Expr::Duplicate => true,
+            _ => false,
+        }
+    }
+
+    /// Let the custom predicates see every walked file before any is matched
+    pub fn prepare(&self, records: &[FileRecord]) -> Result<()> {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.prepare(records)?;
+                right.prepare(records)
+            }
+            Expr::Not(inner) => inner.prepare(records),
+            Expr::Custom(predicate) => predicate.prepare(records),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether evaluating the expression needs the git ignore files
+    pub fn uses_git_ignores(&self) -> bool {
+        match self {
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.uses_git_ignores() || right.uses_git_ignores()
+            }
+            Expr::Not(inner) => inner.uses_git_ignores(),
+            Expr::GitIgnored => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for SizeOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SizeOp::Greater => ">",
+            SizeOp::GreaterOrEqual => ">=",
+            SizeOp::Less => "<",
+            SizeOp::LessOrEqual => "<=",
+            SizeOp::Equal => "=",
+        })
+    }
+}
+
+/// Writes the expression back in the expression language, fully parenthesized
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let patterns = |f: &mut fmt::Formatter<'_>, name: &str, matcher: &PathMatcher| {
+            let predicates: Vec<String> = matcher
+                .patterns()
+                .iter()
+                .map(|pattern| format!("{}({})", name, pattern))
+                .collect();
+            match predicates.len() {
+                1 => f.write_str(&predicates[0]),
+                _ => write!(f, "({})", predicates.join(" or ")),
+            }
+        };
+        let time = |date: &DateTime<Utc>| date.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        match self {
+            Expr::And(left, right) => write!(f, "({} and {})", left, right),
+            Expr::Or(left, right) => write!(f, "({} or {})", left, right),
+            Expr::Not(inner) => write!(f, "not {}", inner),
+            Expr::Ext(types) => write!(f, "ext({})", types.join(",")),
+            Expr::Size(op, size) => write!(f, "size{}{}", op, size),
+            Expr::Older(date, field) => write!(f, "older({}, {})", time(date), field),
+            Expr::Newer(date, field) => write!(f, "newer({}, {})", time(date), field),
+            Expr::Name(matcher) => patterns(f, "name", matcher),
+            Expr::Path(matcher) => patterns(f, "path", matcher),
+            Expr::Duplicate => f.write_str("dup()"),
+            Expr::GitIgnored => f.write_str("ignored()"),
+            Expr::Custom(predicate) => write!(f, "{:?}", predicate),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Op(SizeOp),
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+                // predicate arguments are taken verbatim up to the closing parenthesis
+                if matches!(tokens.iter().rev().nth(1), Some(Token::Word(_))) {
+                    let start = i;
+                    while i < chars.len() && chars[i] != ')' {
+                        i += 1;
+                    }
+                    if i == chars.len() {
+                        bail!("Missing closing parenthesis in filter: {}", input);
+                    }
+                    tokens.push(Token::Word(
+                        chars[start..i]
+                            .iter()
+                            .collect::<String>()
+                            .trim()
+                            .to_string(),
+                    ));
+                }
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '&' | '|' => {
+                if chars.get(i + 1) != Some(&c) {
+                    bail!("Expected {}{} in filter: {}", c, c, input);
+                }
+                tokens.push(if c == '&' { Token::And } else { Token::Or });
+                i += 2;
+            }
+            '>' | '<' | '=' => {
+                let op = match (c, chars.get(i + 1)) {
+                    ('>', Some('=')) => SizeOp::GreaterOrEqual,
+                    ('<', Some('=')) => SizeOp::LessOrEqual,
+                    ('>', _) => SizeOp::Greater,
+                    ('<', _) => SizeOp::Less,
+                    _ => SizeOp::Equal,
+                };
+                i += match op {
+                    SizeOp::GreaterOrEqual | SizeOp::LessOrEqual => 2,
+                    _ => 1,
+                };
+                if op == SizeOp::Equal && chars.get(i) == Some(&'=') {
+                    i += 1;
+                }
+                tokens.push(Token::Op(op));
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && !"()<>=!&|".contains(chars[i])
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(anyhow!(
+                "Expected {:?} in filter, found {:?}",
+                expected,
+                other
+            )),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = expr.and(self.parse_not()?);
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.parse_or()?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            Some(Token::Word(name)) => self.parse_predicate(&name),
+            other => Err(anyhow!("Expected a filter, found {:?}", other)),
+        }
+    }
+
+    fn parse_predicate(&mut self, name: &str) -> Result<Expr> {
+        if let (true, Some(Token::Op(op))) = (name == "size", self.peek()) {
+            let op = *op;
+            self.next();
+            let value = match self.next() {
+                Some(Token::Word(value)) => value,
+                other => bail!("Expected a size, found {:?}", other),
+            };
+            return Ok(Expr::Size(op, parse_size(&value)?));
+        }
+
+        self.expect(Token::Open)?;
+        // `dup()` and `ignored()` have no argument, so the closing parenthesis may follow directly
+        let arg = match self.peek() {
+            Some(Token::Word(_)) => match self.next() {
+                Some(Token::Word(arg)) => arg,
+                _ => unreachable!(),
+            },
+            _ => String::new(),
+        };
+        self.expect(Token::Close)?;
+
+        match name {
+            "ext" | "type" => Ok(Expr::Ext(
+                arg.split(',')
+                    .map(|t| t.trim().trim_start_matches('.').to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect(),
+            )),
+            "size" => match parse_size_range(&arg)? {
+                (Some(min), Some(max)) => Ok(Expr::Size(SizeOp::GreaterOrEqual, min)
+                    .and(Expr::Size(SizeOp::LessOrEqual, max))),
+                (Some(min), None) => Ok(Expr::Size(SizeOp::GreaterOrEqual, min)),
+                (None, Some(max)) => Ok(Expr::Size(SizeOp::LessOrEqual, max)),
+                (None, None) => Err(anyhow!("Expected a size range in filter: size({})", arg)),
+            },
+            "older" | "newer" => {
+                // an optional second argument picks the timestamp, e.g. `older(30d, atime)`
+                let (date, field) = match arg.split_once(',') {
+                    Some((date, field)) => (date.trim(), field.trim().parse()?),
+                    None => (arg.as_str(), TimeField::default()),
+                };
+                let date = parse_cutoff_date(date)?;
+                Ok(if name == "older" {
+                    Expr::Older(date, field)
+                } else {
+                    Expr::Newer(date, field)
+                })
+            }
+            "name" => Ok(Expr::Name(PathMatcher::new(&[arg], None)?)),
+            "path" => Ok(Expr::Path(PathMatcher::new(&[arg], None)?)),
+            "dup" | "duplicate" => Ok(Expr::Duplicate),
+            "ignored" => Ok(Expr::GitIgnored),
+            _ => Err(anyhow!("Unknown filter: {}", name)),
+        }
+    }
+}
+
+/// Parse a filter expression. Predicates are `ext(log,tmp)`, `size>10M` (also `>=`, `<`, `<=`
+/// and `=`), `size(10M..2G)`, `older(30d)`, `newer(2024-01-01)`, `name(*.bak)`,
+/// `path(**/build/*.o)`, `dup()` and `ignored()`, combined with `and`, `or`, `not` (or `&&`,
+/// `||`, `!`) and parentheses. `older` and `newer` take an optional time field, e.g.
+/// `older(30d, atime)`.
+pub fn parse_expression(input: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let expr = parser.parse_or()?;
+    if let Some(token) = parser.peek() {
+        bail!("Unexpected {:?} in filter: {}", token, input);
+    }
+    Ok(expr)
+}
+
+/// `-e "ext(log) and size>10M"` cleans files matching the expression
+pub struct ExpressionFilter;
+
+impl Filter for ExpressionFilter {
+    fn name(&self) -> &'static str {
+        "filter"
+    }
+
+    fn description(&self) -> &'static str {
+        "filter"
+    }
+
+    fn arg(&self) -> Arg {
+        Arg::new(self.name())
+            .short('e')
+            .long("filter")
+            .required(false)
+            .value_parser(clap::value_parser!(String))
+            .help("Filter expression, e.g. \"ext(log) and size>1000 and older(2024-01-01)\". Combined with the other filters using and")
+    }
+
+    fn parse(&self, values: &[String], _context: &FilterContext) -> Result<Option<Expr>> {
+        if values.is_empty() {
+            return Ok(None);
+        }
+        // a config file line is split on spaces
+        parse_expression(&values.join(" ")).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn record(path: &std::path::Path) -> FileRecord {
+        FileRecord {
+            path: path.to_path_buf(),
+            relative_path: PathBuf::from(path.file_name().unwrap()),
+            metadata: fs::metadata(path).unwrap(),
+            git_ignored: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_expression_precedence() -> Result<()> {
+        let expr = parse_expression("ext(log) or ext(tmp) and not size>10")?;
+        assert_eq!(
+            expr,
+            Expr::Or(
+                Box::new(Expr::Ext(vec!["log".to_string()])),
+                Box::new(Expr::And(
+                    Box::new(Expr::Ext(vec!["tmp".to_string()])),
+                    Box::new(Expr::Not(Box::new(Expr::Size(SizeOp::Greater, 10)))),
+                )),
+            )
+        );
+        assert_eq!(
+            parse_expression("(dup() || name(*.bak)) && size>=1")?,
+            Expr::And(
+                Box::new(Expr::Or(
+                    Box::new(Expr::Duplicate),
+                    Box::new(Expr::Name(PathMatcher::new(&["*.bak".to_string()], None)?)),
+                )),
+                Box::new(Expr::Size(SizeOp::GreaterOrEqual, 1)),
+            )
+        );
+        assert!(parse_expression("ext(log) and").is_err());
+        assert!(parse_expression("ext(log").is_err());
+        assert!(parse_expression("size>lots").is_err());
+        assert_eq!(
+            parse_expression("size(1K..2K)")?,
+            Expr::And(
+                Box::new(Expr::Size(SizeOp::GreaterOrEqual, 1024)),
+                Box::new(Expr::Size(SizeOp::LessOrEqual, 2048)),
+            )
+        );
+        assert_eq!(
+            parse_expression("size>1.5K")?,
+            Expr::Size(SizeOp::Greater, 1536)
+        );
+        assert!(parse_expression("colour(red)").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expression_evaluation() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let log = temp_dir.path().join("app.log");
+        File::create(&log)?.set_len(2000)?;
+        let log = record(&log);
+
+        assert!(parse_expression("ext(log) and size>1000")?.eval(&log, false));
+        assert!(!parse_expression("ext(log) and size<1000")?.eval(&log, false));
+        assert!(parse_expression("ext(txt) or name(app.*)")?.eval(&log, false));
+        assert!(!parse_expression("not ext(log)")?.eval(&log, false));
+        assert!(parse_expression("newer(2000-01-01)")?.eval(&log, false));
+        assert!(!parse_expression("older(2000-01-01)")?.eval(&log, false));
+        assert!(parse_expression("newer(1d, ctime)")?.eval(&log, false));
+        assert!(!parse_expression("older(2000-01-01T00:00:00Z, mtime)")?.eval(&log, false));
+        assert!(parse_expression("older(1d, someday)").is_err());
+        assert!(!parse_expression("dup()")?.eval(&log, false));
+        assert!(parse_expression("dup()")?.eval(&log, true));
+        assert!(!parse_expression("ignored()")?.eval(&log, false));
+        assert!(parse_expression("not ignored() and dup()")?.uses_git_ignores());
+
+        let explain = |expression: &str| -> Result<Vec<String>> {
+            Ok(parse_expression(expression)?.explain(&log, false))
+        };
+        assert_eq!(
+            explain("ext(log) and (size<10 or name(app.*))")?,
+            vec!["ext(log)", "name(app.*)"]
+        );
+        assert_eq!(explain("size<10 and ext(log)")?, vec!["size<10"]);
+        assert_eq!(explain("ext(txt) or size<10")?, vec!["ext(txt)", "size<10"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_name_and_path_patterns() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("app-01.log");
+        fs::write(&path, "content")?;
+        let record = FileRecord {
+            path: path.clone(),
+            relative_path: PathBuf::from("logs/2024/app-01.log"),
+            metadata: fs::metadata(&path)?,
+            git_ignored: false,
+        };
+
+        assert!(parse_expression("name(app-??.log)")?.eval(&record, false));
+        assert!(!parse_expression("name(*.txt)")?.eval(&record, false));
+        assert!(parse_expression("path(logs/**/*.log)")?.eval(&record, false));
+        assert!(parse_expression("path(2024)")?.eval(&record, false));
+        assert!(!parse_expression("path(/2024)")?.eval(&record, false));
+        assert!(parse_expression(r"path(re:^logs/\d+/)")?.eval(&record, false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_display_parses_back() -> Result<()> {
+        let expr = parse_expression(
+            "ext(log,tmp) and (size>=10 or not newer(2024-01-01, atime)) and path(/build)",
+        )?;
+        assert_eq!(
+            expr.to_string(),
+            "((ext(log,tmp) and (size>=10 or not newer(2024-01-01T00:00:00Z, atime))) \
+             and path(/build))"
+        );
+        assert_eq!(parse_expression(&expr.to_string())?, expr);
+
+        Ok(())
+    }
+}
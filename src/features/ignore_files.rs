@@ -0,0 +1,270 @@
+use anyhow::Result;
+use clap::Arg;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::filter::{flag_enabled, Filter, FilterContext};
+use super::filter_expression::Expr;
+
+/// Project-specific ignore file, paths it matches are never deleted
+pub const CLEANER_IGNORE_FILE: &str = ".cleanerignore";
+
+/// The ignore files read at one level, highest precedence first
+type Level = Vec<Gitignore>;
+
+/// Reads ignore files hierarchically the way git and ripgrep do: every directory may contain
+/// its own ignore files, whose patterns are relative to that directory, and the deepest
+/// matching pattern decides. `!pattern` re-includes a path, except when a parent directory is
+/// ignored.
+///
+/// - `.cleanerignore` protects paths from every cleaner
+/// - `.ignore`, `.gitignore` and `.git/info/exclude` describe what git would ignore. They are
+///   also read in the parent directories of the cleaned one, up to the repository root.
+#[derive(Debug)]
+pub struct IgnoreFiles {
+    /// Absolute path of the cleaned directory, the matchers are built against it
+    root: PathBuf,
+    read_git_files: bool,
+    /// Git ignore files of the directories above the root, deepest first
+    parents: Vec<Level>,
+    cleaner: HashMap<PathBuf, Level>,
+    git: HashMap<PathBuf, Level>,
+    /// Decisions made for directories, a path inside an ignored directory is ignored as well
+    protected_dirs: HashMap<PathBuf, bool>,
+    git_ignored_dirs: HashMap<PathBuf, bool>,
+}
+
+impl IgnoreFiles {
+    /// Prepare to read the ignore files under `directory`. Git ignore files are only read when
+    /// `read_git_files` is set.
+    pub fn new(directory: &Path, read_git_files: bool) -> Self {
+        let root = fs::canonicalize(directory).unwrap_or_else(|_| directory.to_path_buf());
+        let mut parents = vec![];
+        if read_git_files && !root.join(".git").exists() {
+            for dir in root.ancestors().skip(1) {
+                parents.push(Self::read_git_level(dir));
+                if dir.join(".git").exists() {
+                    break;
+                }
+            }
+            // outside of a repository the parent directories have nothing to say
+            if !root.ancestors().any(|dir| dir.join(".git").exists()) {
+                parents.clear();
+            }
+        }
+
+        IgnoreFiles {
+            root,
+            read_git_files,
+            parents,
+            cleaner: HashMap::new(),
+            git: HashMap::new(),
+            protected_dirs: HashMap::new(),
+            git_ignored_dirs: HashMap::new(),
+        }
+    }
+
+    /// Whether a `.cleanerignore` protects the path, given relative to the cleaned directory
+    pub fn is_protected(&mut self, relative_path: &Path, is_dir: bool) -> bool {
+        if relative_path.file_name() == Some(CLEANER_IGNORE_FILE.as_ref()) {
+            return true;
+        }
+        let root = self.root.clone();
+        Self::decide(
+            &root,
+            relative_path,
+            is_dir,
+            &mut self.cleaner,
+            &mut self.protected_dirs,
+            &[],
+            &|dir| Self::read_level(dir, &[CLEANER_IGNORE_FILE]),
+        )
+    }
+
+    /// Whether git would ignore the path, given relative to the cleaned directory
+    pub fn is_git_ignored(&mut self, relative_path: &Path, is_dir: bool) -> bool {
+        if !self.read_git_files {
+            return false;
+        }
+        let root = self.root.clone();
+        Self::decide(
+            &root,
+            relative_path,
+            is_dir,
+            &mut self.git,
+            &mut self.git_ignored_dirs,
+            &self.parents,
+            &Self::read_git_level,
+        )
+    }
+
+    fn decide(
+        root: &Path,
+        relative_path: &Path,
+        is_dir: bool,
+        levels: &mut HashMap<PathBuf, Level>,
+        dirs: &mut HashMap<PathBuf, bool>,
+        parents: &[Level],
+        read: &dyn Fn(&Path) -> Level,
+    ) -> bool {
+        let parent = relative_path.parent().unwrap_or(Path::new(""));
+        let parent_ignored = !parent.as_os_str().is_empty()
+            && match dirs.get(parent) {
+                Some(ignored) => *ignored,
+                None => Self::decide(root, parent, true, levels, dirs, parents, read),
+            };
+
+        let ignored = parent_ignored || {
+            let path = root.join(relative_path);
+            let mut decision = None;
+            // the deepest directory containing the path comes first
+            for dir in parent.ancestors() {
+                let level = levels
+                    .entry(dir.to_path_buf())
+                    .or_insert_with(|| read(&root.join(dir)));
+                decision = Self::matched(level, &path, is_dir);
+                if decision.is_some() {
+                    break;
+                }
+            }
+            if decision.is_none() {
+                decision = parents
+                    .iter()
+                    .find_map(|level| Self::matched(level, &path, is_dir));
+            }
+            decision.unwrap_or(false)
+        };
+
+        if is_dir {
+            dirs.insert(relative_path.to_path_buf(), ignored);
+        }
+        ignored
+    }
+
+    fn matched(level: &Level, path: &Path, is_dir: bool) -> Option<bool> {
+        level
+            .iter()
+            .find_map(|matcher| match matcher.matched(path, is_dir) {
+                Match::None => None,
+                Match::Ignore(_) => Some(true),
+                Match::Whitelist(_) => Some(false),
+            })
+    }
+
+    fn read_git_level(dir: &Path) -> Level {
+        let mut level = Self::read_level(dir, &[".ignore", ".gitignore"]);
+        let exclude = dir.join(".git").join("info").join("exclude");
+        if exclude.is_file() {
+            level.extend(Self::build(dir, &exclude));
+        }
+        level
+    }
+
+    fn read_level(dir: &Path, names: &[&str]) -> Level {
+        names
+            .iter()
+            .map(|name| dir.join(name))
+            .filter(|file| file.is_file())
+            .filter_map(|file| Self::build(dir, &file))
+            .collect()
+    }
+
+    fn build(dir: &Path, file: &Path) -> Option<Gitignore> {
+        let mut builder = GitignoreBuilder::new(dir);
+        if let Some(err) = builder.add(file) {
+            eprintln!("Error reading ignore file: {:?}, Error: {}", file, err);
+        }
+        match builder.build() {
+            Ok(matcher) => Some(matcher),
+            Err(err) => {
+                eprintln!("Error reading ignore file: {:?}, Error: {}", file, err);
+                None
+            }
+        }
+    }
+}
+
+/// `--only-ignored true` only cleans files git would ignore
+pub struct IgnoredFilter;
+
+impl Filter for IgnoredFilter {
+    fn name(&self) -> &'static str {
+        "only_ignored"
+    }
+
+    fn description(&self) -> &'static str {
+        "files ignored by git"
+    }
+
+    fn arg(&self) -> Arg {
+        Arg::new(self.name())
+            .long("only-ignored")
+            .required(false)
+            .value_parser(clap::value_parser!(bool))
+            .help("Only clean files ignored by .gitignore, .ignore or .git/info/exclude")
+    }
+
+    fn is_flag(&self) -> bool {
+        true
+    }
+
+    fn parse(&self, values: &[String], _context: &FilterContext) -> Result<Option<Expr>> {
+        Ok(flag_enabled(values)?.then_some(Expr::GitIgnored))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_cleanerignore_is_read_hierarchically() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a/b"))?;
+        fs::write(root.join(CLEANER_IGNORE_FILE), "*.keep\n/data/\n")?;
+        fs::write(
+            root.join("a/b").join(CLEANER_IGNORE_FILE),
+            "!x.keep\n*.cfg\n",
+        )?;
+
+        let mut ignores = IgnoreFiles::new(root, false);
+        assert!(ignores.is_protected(Path::new("one.keep"), false));
+        assert!(ignores.is_protected(Path::new("a/two.keep"), false));
+        assert!(!ignores.is_protected(Path::new("a/b/x.keep"), false));
+        assert!(ignores.is_protected(Path::new("a/b/app.cfg"), false));
+        assert!(!ignores.is_protected(Path::new("app.cfg"), false));
+        assert!(ignores.is_protected(Path::new("data/file.txt"), false));
+        assert!(ignores.is_protected(Path::new(CLEANER_IGNORE_FILE), false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_git_ignore_files() -> anyhow::Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join(".git/info"))?;
+        fs::create_dir_all(root.join("src"))?;
+        fs::write(root.join(".gitignore"), "target/\n*.o\n")?;
+        fs::write(root.join(".ignore"), "!main.o\n")?;
+        fs::write(root.join(".git/info/exclude"), "*.swp\n")?;
+
+        let mut ignores = IgnoreFiles::new(root, true);
+        assert!(ignores.is_git_ignored(Path::new("target/debug/app"), false));
+        assert!(ignores.is_git_ignored(Path::new("src/lib.o"), false));
+        assert!(!ignores.is_git_ignored(Path::new("src/main.o"), false));
+        assert!(ignores.is_git_ignored(Path::new("src/.lib.rs.swp"), false));
+        assert!(!ignores.is_git_ignored(Path::new("src/lib.rs"), false));
+
+        // the parent directories are read up to the repository root
+        let mut ignores = IgnoreFiles::new(&root.join("src"), true);
+        assert!(ignores.is_git_ignored(Path::new("lib.o"), false));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use colored::*;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use super::cleaner_file_duplicate::DuplicateSet;
+use super::scanner::FileRecord;
+use super::utils::format_size;
+
+/// Files decided on together: one file, the files of a directory, or the copies of a duplicate
+enum Unit {
+    File(usize),
+    Directory(PathBuf, Vec<usize>),
+    Duplicates(usize, Vec<usize>),
+}
+
+impl Unit {
+    fn files(&self) -> &[usize] {
+        match self {
+            Unit::File(idx) => std::slice::from_ref(idx),
+            Unit::Directory(_, files) | Unit::Duplicates(_, files) => files,
+        }
+    }
+}
+
+/// Ask which of the selected files the action is applied to, unselecting the rejected ones.
+/// All of them can be accepted or rejected at once, or decided on file by file or directory by
+/// directory; the copies of a duplicate are always decided on together. Running out of input
+/// rejects whatever is left.
+pub fn confirm(
+    records: &[FileRecord],
+    selected: &mut [bool],
+    duplicate_sets: &[DuplicateSet],
+    input: &mut impl BufRead,
+) -> Result<()> {
+    let candidates: Vec<usize> = (0..records.len()).filter(|idx| selected[*idx]).collect();
+    if candidates.is_empty() {
+        return Ok(());
+    }
+    let total: u64 = candidates
+        .iter()
+        .map(|idx| records[*idx].metadata.len())
+        .sum();
+    eprintln!(
+        "\n{} files selected, {}",
+        candidates.len().to_string().bold(),
+        format_size(total)
+    );
+
+    let by_directory = loop {
+        match ask(
+            "Apply the action to [y] all, [n] none, [f] file by file, [d] directory by directory?",
+            input,
+        )?
+        .as_deref()
+        {
+            Some("y") => return Ok(()),
+            Some("n") | None => {
+                reject(selected, &candidates);
+                return Ok(());
+            }
+            Some("f") => break false,
+            Some("d") => break true,
+            _ => continue,
+        }
+    };
+
+    let mut units = vec![];
+    let mut grouped = vec![false; records.len()];
+    for set in duplicate_sets {
+        let copies: Vec<usize> = set
+            .copies
+            .iter()
+            .copied()
+            .filter(|idx| selected[*idx])
+            .collect();
+        for idx in &copies {
+            grouped[*idx] = true;
+        }
+        if !copies.is_empty() {
+            units.push(Unit::Duplicates(set.keeper, copies));
+        }
+    }
+    let rest = candidates.into_iter().filter(|idx| !grouped[*idx]);
+    if by_directory {
+        let mut directories: BTreeMap<PathBuf, Vec<usize>> = BTreeMap::new();
+        for idx in rest {
+            let parent = records[idx].path.parent().unwrap_or(Path::new(""));
+            directories
+                .entry(parent.to_path_buf())
+                .or_default()
+                .push(idx);
+        }
+        units.extend(
+            directories
+                .into_iter()
+                .map(|(dir, files)| Unit::Directory(dir, files)),
+        );
+    } else {
+        units.extend(rest.map(Unit::File));
+    }
+
+    for (position, unit) in units.iter().enumerate() {
+        eprintln!();
+        match unit {
+            Unit::File(idx) => eprintln!("{}", preview(&records[*idx])),
+            Unit::Directory(dir, files) => {
+                eprintln!("{}", format!("{}/", dir.display()).bold());
+                for idx in files {
+                    eprintln!("  {}", preview(&records[*idx]));
+                }
+            }
+            Unit::Duplicates(keeper, copies) => {
+                eprintln!(
+                    "{} {}",
+                    "Duplicates of".bold(),
+                    records[*keeper].path.display()
+                );
+                for idx in copies {
+                    eprintln!("  {}", preview(&records[*idx]));
+                }
+            }
+        }
+        loop {
+            match ask(
+                "Apply the action? [y] yes, [n] no, [a] yes to this and all the rest, [q] no to this and all the rest",
+                input,
+            )?
+            .as_deref()
+            {
+                Some("y") => break,
+                Some("n") => {
+                    reject(selected, unit.files());
+                    break;
+                }
+                Some("a") => return Ok(()),
+                Some("q") | None => {
+                    for unit in &units[position..] {
+                        reject(selected, unit.files());
+                    }
+                    return Ok(());
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn reject(selected: &mut [bool], files: &[usize]) {
+    for idx in files {
+        selected[*idx] = false;
+    }
+}
+
+/// Print the question and read the answer, `None` once the input is exhausted
+fn ask(question: &str, input: &mut impl BufRead) -> Result<Option<String>> {
+    eprintln!("{}", question.cyan());
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_lowercase()))
+}
+
+fn preview(record: &FileRecord) -> String {
+    let modified = record
+        .metadata
+        .modified()
+        .map(|time| {
+            DateTime::<Local>::from(time)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|_| "unknown".to_string());
+    format!(
+        "{}  {}, modified {}",
+        record.path.display(),
+        format_size(record.metadata.len()),
+        modified
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    fn records(paths: &[PathBuf]) -> Result<Vec<FileRecord>> {
+        paths
+            .iter()
+            .map(|path| {
+                fs::create_dir_all(path.parent().unwrap())?;
+                fs::write(path, "content")?;
+                Ok(FileRecord {
+                    path: path.clone(),
+                    relative_path: path.clone(),
+                    metadata: fs::metadata(path)?,
+                    git_ignored: false,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_confirm_file_by_file() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let paths: Vec<PathBuf> = ["a.log", "b.log", "c.log", "d.log"]
+            .iter()
+            .map(|name| temp_dir.path().join(name))
+            .collect();
+        let records = records(&paths)?;
+
+        let mut selected = vec![true, true, true, false];
+        confirm(
+            &records,
+            &mut selected,
+            &[],
+            &mut Cursor::new("f\ny\nmaybe\nn\ny\n"),
+        )?;
+        assert_eq!(selected, vec![true, false, true, false]);
+
+        let mut selected = vec![true, true, true, true];
+        confirm(&records, &mut selected, &[], &mut Cursor::new("f\nn\na\n"))?;
+        assert_eq!(selected, vec![false, true, true, true]);
+
+        // running out of answers rejects the rest
+        let mut selected = vec![true, true, true, true];
+        confirm(&records, &mut selected, &[], &mut Cursor::new("f\ny\n"))?;
+        assert_eq!(selected, vec![true, false, false, false]);
+
+        let mut selected = vec![true, true, true, true];
+        confirm(&records, &mut selected, &[], &mut Cursor::new("n\n"))?;
+        assert_eq!(selected, vec![false; 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_confirm_by_directory_and_duplicate_group() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let paths: Vec<PathBuf> = [
+            "logs/a.log",
+            "logs/b.log",
+            "tmp/c.tmp",
+            "keep.txt",
+            "copy.txt",
+        ]
+        .iter()
+        .map(|name| temp_dir.path().join(name))
+        .collect();
+        let records = records(&paths)?;
+        let sets = [DuplicateSet {
+            keeper: 3,
+            copies: vec![4],
+            action: Default::default(),
+        }];
+
+        // the duplicate group comes first, then logs/ and tmp/
+        let mut selected = vec![true, true, true, false, true];
+        confirm(
+            &records,
+            &mut selected,
+            &sets,
+            &mut Cursor::new("d\nn\ny\nq\n"),
+        )?;
+        assert_eq!(selected, vec![true, true, false, false, false]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,516 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::report::Report;
+
+use super::archive;
+use super::cleaner_file_duplicate::replace_with_copy;
+use super::compress::Compression;
+use super::quarantine;
+use super::trash::restore_from_trash;
+
+/// Name of a new run, sortable by the time it started
+pub fn new_run_id() -> String {
+    Utc::now().format("%Y%m%d-%H%M%S-%6f").to_string()
+}
+
+/// Where journals are kept by default, `$XDG_STATE_HOME/dir-cleaner-rust/journal` or
+/// `~/.local/state/dir-cleaner-rust/journal`
+pub fn default_journal_dir() -> Result<PathBuf> {
+    let state_home = match env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".local").join("state"))
+            .ok_or_else(|| anyhow!("Could not find the journal directory, HOME is not set"))?,
+    };
+    Ok(state_home.join("dir-cleaner-rust").join("journal"))
+}
+
+/// What was done to a path, with what it takes to reverse it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Operation {
+    /// Removed for good, can't be undone
+    Delete,
+    /// Overwritten with this many passes and removed, can't be undone
+    Shred { passes: usize },
+    /// Moved to the trash
+    Trash { trashed_path: PathBuf },
+    /// Moved into a quarantine, `quarantined_path` is relative to its root
+    Quarantine {
+        quarantine: PathBuf,
+        quarantined_path: PathBuf,
+    },
+    /// Written to an archive as `entry` and deleted
+    Archive { archive: PathBuf, entry: PathBuf },
+    /// Replaced with a compressed sibling
+    Compress {
+        compressed_path: PathBuf,
+        compressed_size: u64,
+    },
+    /// Duplicate replaced with a hardlink to the keeper
+    Hardlink { keeper: PathBuf },
+    /// Duplicate replaced with a reflink to the keeper
+    Reflink { keeper: PathBuf },
+}
+
+/// A path a run acted on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    #[serde(flatten)]
+    pub operation: Operation,
+}
+
+impl JournalEntry {
+    /// The entry with absolute paths, journals are read from anywhere
+    pub fn absolute(&self) -> JournalEntry {
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or(path.to_path_buf());
+        JournalEntry {
+            path: absolute(&self.path),
+            size: self.size,
+            operation: match &self.operation {
+                Operation::Quarantine {
+                    quarantine,
+                    quarantined_path,
+                } => Operation::Quarantine {
+                    quarantine: absolute(quarantine),
+                    quarantined_path: quarantined_path.clone(),
+                },
+                Operation::Archive { archive, entry } => Operation::Archive {
+                    archive: absolute(archive),
+                    entry: entry.clone(),
+                },
+                Operation::Compress {
+                    compressed_path,
+                    compressed_size,
+                } => Operation::Compress {
+                    compressed_path: absolute(compressed_path),
+                    compressed_size: *compressed_size,
+                },
+                Operation::Hardlink { keeper } => Operation::Hardlink {
+                    keeper: absolute(keeper),
+                },
+                Operation::Reflink { keeper } => Operation::Reflink {
+                    keeper: absolute(keeper),
+                },
+                operation => operation.clone(),
+            },
+        }
+    }
+}
+
+/// Record of a run that touched the disk, written so the run can be undone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    pub run_id: String,
+    pub started_at: DateTime<Utc>,
+    pub root: PathBuf,
+    /// The filters the files were selected by, in the expression language
+    pub filters: Vec<String>,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Journal of a run that just finished, with a new id and absolute paths
+    pub fn new(
+        started_at: DateTime<Utc>,
+        root: &Path,
+        filters: Vec<String>,
+        entries: &[JournalEntry],
+    ) -> Journal {
+        Journal {
+            run_id: new_run_id(),
+            started_at,
+            root: std::path::absolute(root).unwrap_or(root.to_path_buf()),
+            filters,
+            entries: entries.iter().map(JournalEntry::absolute).collect(),
+        }
+    }
+
+    fn path(dir: &Path, run_id: &str) -> PathBuf {
+        dir.join(format!("{}.json", run_id))
+    }
+
+    /// Write the journal to `<dir>/<run id>.json`, never replacing another run's journal
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create journal directory: {:?}", dir))?;
+        let path = Self::path(dir, &self.run_id);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create journal: {:?}", path))?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(path)
+    }
+
+    /// Read the journal of a run
+    pub fn read(dir: &Path, run_id: &str) -> Result<Journal> {
+        let path = Self::path(dir, run_id);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(anyhow!("No journal for run {} in {:?}", run_id, dir))
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read journal: {:?}", path))
+            }
+        };
+        serde_json::from_str(&content).with_context(|| format!("Invalid journal: {:?}", path))
+    }
+
+    /// Replace the journal with what is left of it, removing it once it's empty
+    fn rewrite(&self, dir: &Path) -> Result<()> {
+        let path = Self::path(dir, &self.run_id);
+        if self.entries.is_empty() {
+            fs::remove_file(&path)?;
+            return Ok(());
+        }
+        let temp = dir.join(format!("{}.json.tmp", self.run_id));
+        fs::write(&temp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+}
+
+/// Journal what a run did to the directory given, if any, recording the run id in the report.
+/// A run that failed partway is journaled too when it already touched the disk, so that what was
+/// done before the failure can still be undone.
+pub fn journal_run<T>(
+    outcome: Result<T>,
+    dir: Option<&Path>,
+    started_at: DateTime<Utc>,
+    root: &Path,
+    filters: Vec<String>,
+    report: &mut Report,
+) -> Result<T> {
+    let Some(dir) = dir else {
+        return outcome;
+    };
+    if outcome.is_err() && report.journal.is_empty() {
+        return outcome;
+    }
+    let journal = Journal::new(started_at, root, filters, &report.journal);
+    let written = journal.write(dir).context("Failed to write the journal");
+    if written.is_ok() {
+        report.run_id = Some(journal.run_id.clone());
+    }
+    match (outcome, written) {
+        (Ok(value), Ok(_)) => Ok(value),
+        (Ok(_), Err(err)) => Err(err),
+        (Err(err), Ok(_)) => Err(err.context(format!(
+            "Run {} failed partway, what was done before is journaled",
+            journal.run_id
+        ))),
+        (Err(err), Err(write_err)) => {
+            eprintln!("{:#}", write_err);
+            Err(err)
+        }
+    }
+}
+
+/// Entries of an undone run, split by whether they could be reversed
+#[derive(Debug, Default)]
+pub struct UndoReport {
+    pub undone: Vec<JournalEntry>,
+    /// Entries left as they are, with the reason
+    pub failed: Vec<(JournalEntry, String)>,
+}
+
+/// Reverse what a run did. Deleted files can't be brought back and a path that exists again is
+/// never overwritten; those entries stay in the journal and are reported as failed.
+pub fn undo(dir: &Path, run_id: &str, dry_run: bool) -> Result<UndoReport> {
+    let mut journal = Journal::read(dir, run_id)?;
+    let mut report = UndoReport::default();
+
+    // quarantined files are restored through the quarantine so its manifest stays in sync
+    let mut quarantined: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for entry in &journal.entries {
+        if let Operation::Quarantine {
+            quarantine,
+            quarantined_path,
+        } = &entry.operation
+        {
+            quarantined
+                .entry(quarantine.clone())
+                .or_default()
+                .push(quarantined_path.clone());
+        }
+    }
+    let mut restored = vec![];
+    for (root, paths) in &quarantined {
+        match quarantine::restore_paths(root, paths, dry_run) {
+            Ok(entries) => restored.extend(
+                entries
+                    .into_iter()
+                    .map(|entry| (root.clone(), entry.quarantined_path)),
+            ),
+            Err(err) => eprintln!("Could not restore from {:?}, proceeding: {:#}", root, err),
+        }
+    }
+
+    // each archive is read once for all the files extracted from it
+    let mut archived: HashMap<PathBuf, HashMap<PathBuf, PathBuf>> = HashMap::new();
+    for entry in &journal.entries {
+        if let Operation::Archive {
+            archive,
+            entry: name,
+        } = &entry.operation
+        {
+            archived
+                .entry(archive.clone())
+                .or_default()
+                .insert(name.clone(), entry.path.clone());
+        }
+    }
+    let mut extracted = vec![];
+    for (archive, destinations) in &archived {
+        match archive::extract(archive, destinations, dry_run) {
+            Ok(names) => extracted.extend(names.into_iter().map(|name| (archive.clone(), name))),
+            Err(err) => eprintln!(
+                "Could not extract from {:?}, proceeding: {:#}",
+                archive, err
+            ),
+        }
+    }
+
+    for entry in journal.entries.drain(..) {
+        let result = match &entry.operation {
+            Operation::Delete => Err(anyhow!("deleted permanently")),
+            Operation::Shred { .. } => Err(anyhow!("shredded")),
+            Operation::Quarantine {
+                quarantine,
+                quarantined_path,
+            } => {
+                let key = (quarantine.clone(), quarantined_path.clone());
+                if restored.contains(&key) {
+                    Ok(())
+                } else {
+                    Err(anyhow!("still in quarantine at {:?}", quarantine))
+                }
+            }
+            Operation::Archive {
+                archive,
+                entry: name,
+            } => {
+                if extracted.contains(&(archive.clone(), name.clone())) {
+                    Ok(())
+                } else {
+                    Err(anyhow!("still in the archive {:?}", archive))
+                }
+            }
+            _ if dry_run => {
+                eprintln!(
+                    "\n {} could have been restored",
+                    entry.path.display().to_string().bold().yellow()
+                );
+                Ok(())
+            }
+            Operation::Trash { trashed_path } => restore_from_trash(trashed_path, &entry.path),
+            Operation::Compress {
+                compressed_path, ..
+            } => compressed_path
+                .extension()
+                .map(|ext| ext.to_string_lossy().parse::<Compression>())
+                .unwrap_or_else(|| Err(anyhow!("Unknown compression: {:?}", compressed_path)))
+                .and_then(|compression| compression.decompress_file(compressed_path, &entry.path)),
+            Operation::Hardlink { keeper } | Operation::Reflink { keeper } => {
+                replace_with_copy(keeper, &entry.path)
+            }
+        };
+        match result {
+            Ok(()) => report.undone.push(entry),
+            Err(err) => report.failed.push((entry, format!("{:#}", err))),
+        }
+    }
+
+    if !dry_run {
+        journal.entries = report
+            .failed
+            .iter()
+            .map(|(entry, _)| entry.clone())
+            .collect();
+        journal.rewrite(dir)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::quarantine::Quarantine;
+    use crate::features::scanner::FileRecord;
+    use crate::features::trash::{move_to_trash, TrashDir};
+    use tempfile::tempdir;
+
+    fn journal(root: &Path, entries: Vec<JournalEntry>) -> Journal {
+        Journal {
+            run_id: new_run_id(),
+            started_at: Utc::now(),
+            root: root.to_path_buf(),
+            filters: vec!["ext(log)".to_string()],
+            entries,
+        }
+    }
+
+    #[test]
+    fn test_journal_round_trip() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let journal = journal(
+            temp_dir.path(),
+            vec![JournalEntry {
+                path: temp_dir.path().join("app.log"),
+                size: 10,
+                operation: Operation::Trash {
+                    trashed_path: PathBuf::from("/trash/files/app.log"),
+                },
+            }],
+        );
+        let path = journal.write(temp_dir.path())?;
+        assert!(fs::read_to_string(path)?.contains("\"action\": \"trash\""));
+        assert_eq!(Journal::read(temp_dir.path(), &journal.run_id)?, journal);
+        assert!(journal.write(temp_dir.path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_reverses_what_it_can() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let data = temp_dir.path().join("data");
+        let journals = temp_dir.path().join("journal");
+        fs::create_dir_all(&data)?;
+        let file = |name: &str| -> Result<PathBuf> {
+            let path = data.join(name);
+            fs::write(&path, name)?;
+            Ok(path)
+        };
+
+        let trashed = file("trashed.txt")?;
+        let trash = TrashDir {
+            path: temp_dir.path().join("Trash"),
+            top_dir: None,
+        };
+        let trashed_path = move_to_trash(&trashed, &trash)?;
+
+        let quarantined = file("quarantined.txt")?;
+        let quarantine = Quarantine::new(temp_dir.path().join("quarantine"));
+        let record = FileRecord {
+            metadata: fs::metadata(&quarantined)?,
+            path: quarantined.clone(),
+            relative_path: PathBuf::from("quarantined.txt"),
+            git_ignored: false,
+        };
+        let quarantined_path = quarantine.quarantine_file(&record, false)?.unwrap();
+
+        let keeper = file("keeper.txt")?;
+        let linked = data.join("linked.txt");
+        fs::hard_link(&keeper, &linked)?;
+
+        let journal = journal(
+            &data,
+            vec![
+                JournalEntry {
+                    path: data.join("deleted.txt"),
+                    size: 1,
+                    operation: Operation::Delete,
+                },
+                JournalEntry {
+                    path: trashed.clone(),
+                    size: 1,
+                    operation: Operation::Trash { trashed_path },
+                },
+                JournalEntry {
+                    path: quarantined.clone(),
+                    size: 1,
+                    operation: Operation::Quarantine {
+                        quarantine: quarantine.root.clone(),
+                        quarantined_path,
+                    },
+                },
+                JournalEntry {
+                    path: linked.clone(),
+                    size: 1,
+                    operation: Operation::Hardlink {
+                        keeper: keeper.clone(),
+                    },
+                },
+            ],
+        );
+        journal.write(&journals)?;
+
+        let report = undo(&journals, &journal.run_id, false)?;
+        assert_eq!(report.undone.len(), 3);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0.operation, Operation::Delete);
+
+        assert_eq!(fs::read_to_string(&trashed)?, "trashed.txt");
+        assert!(!trash.path.join("info/trashed.txt.trashinfo").exists());
+        assert_eq!(fs::read_to_string(&quarantined)?, "quarantined.txt");
+        assert!(quarantine::read_manifest(&quarantine.root)?.is_empty());
+        fs::write(&linked, "changed")?;
+        assert_eq!(fs::read_to_string(&keeper)?, "keeper.txt");
+
+        // only the deleted file is left in the journal
+        let left = Journal::read(&journals, &journal.run_id)?;
+        assert_eq!(left.entries.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_proceeds_past_a_broken_quarantine() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let journals = temp_dir.path().join("journal");
+        let keeper = temp_dir.path().join("keeper.txt");
+        let linked = temp_dir.path().join("linked.txt");
+        fs::write(&keeper, "keeper")?;
+        fs::hard_link(&keeper, &linked)?;
+        let broken = temp_dir.path().join("quarantine");
+        fs::create_dir_all(&broken)?;
+        fs::write(broken.join(quarantine::MANIFEST_FILE), "not json\n")?;
+
+        let journal = journal(
+            temp_dir.path(),
+            vec![
+                JournalEntry {
+                    path: temp_dir.path().join("quarantined.txt"),
+                    size: 1,
+                    operation: Operation::Quarantine {
+                        quarantine: broken,
+                        quarantined_path: PathBuf::from("run/quarantined.txt"),
+                    },
+                },
+                JournalEntry {
+                    path: linked.clone(),
+                    size: 1,
+                    operation: Operation::Hardlink { keeper },
+                },
+            ],
+        );
+        journal.write(&journals)?;
+
+        let report = undo(&journals, &journal.run_id, false)?;
+        assert_eq!(report.undone.len(), 1);
+        assert_eq!(report.failed.len(), 1);
+        assert!(matches!(
+            report.failed[0].0.operation,
+            Operation::Quarantine { .. }
+        ));
+        // the linked copy was undone and left the journal
+        let left = Journal::read(&journals, &journal.run_id)?;
+        assert_eq!(left.entries, vec![report.failed[0].0.clone()]);
+
+        Ok(())
+    }
+}
@@ -1,6 +1,41 @@
 // All features should be registered here
+pub mod action;
+pub mod analyze;
+pub mod archive;
 pub mod cleaner_file_duplicate;
 pub mod cleaner_file_size;
 pub mod cleaner_file_type;
 pub mod cleaner_last_modified_time;
+pub mod compress;
+pub mod filter;
+pub mod filter_expression;
+pub mod ignore_files;
+pub mod interactive;
+pub mod journal;
+pub mod path_matcher;
+pub mod plan;
+pub mod quarantine;
+pub mod scanner;
+pub mod trash;
+pub mod tui;
 pub mod utils;
+
+use filter::Filter;
+
+static FILTERS: &[&dyn Filter] = &[
+    &cleaner_file_type::TypeFilter,
+    &cleaner_file_size::MinSizeFilter,
+    &cleaner_file_size::MaxSizeFilter,
+    &cleaner_file_duplicate::DuplicateFilter,
+    &cleaner_last_modified_time::AgeFilter,
+    &cleaner_last_modified_time::NewerFilter,
+    &path_matcher::MatchFilter,
+    &ignore_files::IgnoredFilter,
+    &filter_expression::ExpressionFilter,
+];
+
+/// Every filter that can be enabled from the command line or a config file, in the order
+/// they're applied
+pub fn registry() -> &'static [&'static dyn Filter] {
+    FILTERS
+}
@@ -0,0 +1,289 @@
+use anyhow::{bail, Context, Result};
+use clap::Arg;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+
+use super::filter::{Filter, FilterContext};
+use super::filter_expression::Expr;
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Glob {
+        matcher: GlobMatcher,
+        /// Matched against the path relative to the root instead of any single file name
+        anchored: bool,
+        /// Only matches directories, and through them everything beneath
+        dir_only: bool,
+    },
+    Regex(Regex),
+}
+
+/// Matches paths relative to the cleaned directory against gitignore-style globs and regexes.
+/// The same matcher is used to protect files and to select them for deletion.
+///
+/// - `*.log` has no slash and matches a file or directory name at any depth
+/// - `/build` or `docs/*.md` contain a slash and are anchored to the cleaned directory
+/// - `**/build/*.o` matches at any depth, `*` never crosses a `/`
+/// - `cache/` only matches directories
+/// - `re:<regex>` is a regex matched against the relative path, using `/` as separator
+///
+/// A pattern matching a directory matches everything beneath it.
+#[derive(Debug, Clone)]
+pub struct PathMatcher {
+    patterns: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+impl PartialEq for PathMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns
+    }
+}
+
+impl PathMatcher {
+    /// Build a matcher for paths under `root`. Patterns naming a path under the root, given the
+    /// way the directory was, absolute or relative to the working directory, are anchored to it.
+    /// A pattern naming an existing path outside the root is an error, as it could never match.
+    pub fn new(patterns: &[String], root: Option<&Path>) -> Result<Self> {
+        let mut rules = vec![];
+        for pattern in patterns {
+            rules.push(Self::build_rule(pattern, root)?);
+        }
+        Ok(PathMatcher {
+            patterns: patterns.to_vec(),
+            rules,
+        })
+    }
+
+    /// The patterns the matcher was built from
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    fn build_rule(pattern: &str, root: Option<&Path>) -> Result<Rule> {
+        if let Some(regex) = pattern.strip_prefix("re:") {
+            let regex =
+                Regex::new(regex).with_context(|| format!("Invalid regex pattern: {}", pattern))?;
+            return Ok(Rule::Regex(regex));
+        }
+
+        let mut glob = pattern.to_string();
+        if let Some(root) = root {
+            if let Some(relative) = relative_to_root(pattern, root)? {
+                glob = format!("/{}", relative.to_string_lossy());
+            }
+        }
+
+        let dir_only = glob.len() > 1 && glob.ends_with('/');
+        let glob = glob.trim_end_matches('/');
+        let anchored = glob.contains('/');
+        let glob = glob.trim_start_matches('/');
+
+        let matcher = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid glob pattern: {}", pattern))?
+            .compile_matcher();
+
+        Ok(Rule::Glob {
+            matcher,
+            anchored,
+            dir_only,
+        })
+    }
+
+    /// Whether a path relative to the cleaned directory, or one of its parent directories,
+    /// matches any pattern
+    pub fn is_match(&self, relative_path: &Path, is_dir: bool) -> bool {
+        relative_path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .enumerate()
+            .any(|(depth, ancestor)| {
+                // every ancestor but the path itself is a directory
+                let ancestor_is_dir = depth > 0 || is_dir;
+                self.rules
+                    .iter()
+                    .any(|rule| Self::rule_matches(rule, ancestor, ancestor_is_dir))
+            })
+    }
+
+    fn rule_matches(rule: &Rule, path: &Path, is_dir: bool) -> bool {
+        match rule {
+            Rule::Glob {
+                matcher,
+                anchored,
+                dir_only,
+            } => {
+                if *dir_only && !is_dir {
+                    return false;
+                }
+                if *anchored {
+                    matcher.is_match(path)
+                } else {
+                    path.file_name().is_some_and(|name| matcher.is_match(name))
+                }
+            }
+            Rule::Regex(regex) => {
+                let path = path
+                    .to_string_lossy()
+                    .replace(std::path::MAIN_SEPARATOR, "/");
+                regex.is_match(&path)
+            }
+        }
+    }
+}
+
+/// The path a pattern names relative to the root, when it names one. Leading `./` is ignored
+/// on both sides, and patterns without glob characters are resolved on disk, so the root and
+/// the pattern may be given one absolute and the other relative.
+fn relative_to_root(pattern: &str, root: &Path) -> Result<Option<PathBuf>> {
+    let path = normalize(Path::new(pattern));
+    let root_given = normalize(root);
+    if !root_given.as_os_str().is_empty() {
+        if let Ok(relative) = path.strip_prefix(&root_given) {
+            return Ok(Some(relative.to_path_buf()));
+        }
+    }
+    let root_resolved = root.canonicalize().unwrap_or(root_given);
+    if let Ok(relative) = path.strip_prefix(&root_resolved) {
+        return Ok(Some(relative.to_path_buf()));
+    }
+
+    // a glob, or a gitignore-style pattern such as `/build` or `docs/readme.md` naming a path
+    // under the root, isn't a path of its own
+    let is_glob = pattern.contains(['*', '?', '[', '{']);
+    let anchored = path.strip_prefix("/").unwrap_or(&path);
+    if is_glob || !pattern.contains('/') || root.join(anchored).exists() {
+        return Ok(None);
+    }
+    let Ok(resolved) = path.canonicalize() else {
+        return Ok(None);
+    };
+    match resolved.strip_prefix(&root_resolved) {
+        Ok(relative) => Ok(Some(relative.to_path_buf())),
+        Err(_) => bail!(
+            "{} is outside of the cleaned directory {}",
+            pattern,
+            root.display()
+        ),
+    }
+}
+
+/// The path without `.` components
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+/// `-m "**/build/*.o"` only cleans paths matching one of the patterns
+pub struct MatchFilter;
+
+impl Filter for MatchFilter {
+    fn name(&self) -> &'static str {
+        "select_patterns"
+    }
+
+    fn description(&self) -> &'static str {
+        "paths matching"
+    }
+
+    fn arg(&self) -> Arg {
+        Arg::new(self.name())
+            .short('m')
+            .long("match")
+            .required(false)
+            .num_args(1..) // Allow multiple values
+            .help("Only clean paths matching one of these patterns (space-separated), same syntax as --files_to_ignore")
+    }
+
+    fn parse(&self, values: &[String], context: &FilterContext) -> Result<Option<Expr>> {
+        if values.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Expr::Path(PathMatcher::new(
+            values,
+            Some(&context.root),
+        )?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(patterns: &[&str]) -> PathMatcher {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        PathMatcher::new(&patterns, Some(Path::new("/data/project"))).unwrap()
+    }
+
+    #[test]
+    fn test_unanchored_patterns_match_names_at_any_depth() {
+        let m = matcher(&["*.log", "text1.txt"]);
+        assert!(m.is_match(Path::new("app.log"), false));
+        assert!(m.is_match(Path::new("a/b/app.log"), false));
+        assert!(m.is_match(Path::new("Documents/texts/text1.txt"), false));
+        assert!(!m.is_match(Path::new("a/app.log.gz"), false));
+    }
+
+    #[test]
+    fn test_anchored_patterns_match_from_the_root() {
+        let m = matcher(&["/build", "docs/*.md", "/data/project/keep"]);
+        assert!(m.is_match(Path::new("build/out.o"), false));
+        assert!(!m.is_match(Path::new("src/build/out.o"), false));
+        assert!(m.is_match(Path::new("docs/readme.md"), false));
+        assert!(!m.is_match(Path::new("docs/api/readme.md"), false));
+        assert!(m.is_match(Path::new("keep/notes.txt"), false));
+    }
+
+    #[test]
+    fn test_double_star_and_directory_only_patterns() {
+        let m = matcher(&["**/build/*.o", "cache/"]);
+        assert!(m.is_match(Path::new("build/main.o"), false));
+        assert!(m.is_match(Path::new("a/b/build/main.o"), false));
+        assert!(!m.is_match(Path::new("a/build/sub/main.o"), false));
+        assert!(m.is_match(Path::new("x/cache/entry"), false));
+        assert!(m.is_match(Path::new("x/cache"), true));
+        assert!(!m.is_match(Path::new("x/cache"), false));
+    }
+
+    #[test]
+    fn test_regex_patterns() {
+        let m = matcher(&[r"re:^logs/\d{4}-\d{2}-\d{2}\.log$"]);
+        assert!(m.is_match(Path::new("logs/2024-01-31.log"), false));
+        assert!(!m.is_match(Path::new("logs/latest.log"), false));
+        assert!(PathMatcher::new(&["re:(".to_string()], None).is_err());
+    }
+
+    #[test]
+    fn test_paths_given_like_the_root_are_anchored() -> Result<()> {
+        let m = PathMatcher::new(&["data/keep.txt".to_string()], Some(Path::new("./data")))?;
+        assert!(m.is_match(Path::new("keep.txt"), false));
+        let m = PathMatcher::new(&["./data/logs/".to_string()], Some(Path::new("data")))?;
+        assert!(m.is_match(Path::new("logs/app.log"), false));
+        assert!(!m.is_match(Path::new("old/logs/app.log"), false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_absolute_paths_are_resolved_against_the_root() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let data = temp_dir.path().join("data");
+        std::fs::create_dir(&data)?;
+        std::fs::write(data.join("keep.txt"), "keep")?;
+        std::fs::write(temp_dir.path().join("other.txt"), "other")?;
+        let root = temp_dir.path().join(".").join("data");
+
+        let keep = data.canonicalize()?.join("keep.txt");
+        let m = PathMatcher::new(&[keep.to_string_lossy().to_string()], Some(&root))?;
+        assert!(m.is_match(Path::new("keep.txt"), false));
+        assert!(!m.is_match(Path::new("sub/keep.txt"), false));
+
+        let outside = temp_dir.path().join("other.txt");
+        let err = PathMatcher::new(&[outside.to_string_lossy().to_string()], Some(&root));
+        assert!(format!("{:#}", err.unwrap_err()).contains("outside of the cleaned directory"));
+        Ok(())
+    }
+}
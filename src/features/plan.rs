@@ -0,0 +1,293 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use super::action::Action;
+use super::archive::Archive;
+use super::cleaner_file_duplicate::full_hash;
+use super::journal::journal_run;
+use super::quarantine::Quarantine;
+use super::scanner::{apply_action, record_decision, FileRecord};
+use crate::report::{Decision, Report};
+
+/// A selected file as it was when the plan was made
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// Inode number, 0 on platforms without one
+    pub inode: u64,
+    /// BLAKE3 hash of the content
+    pub hash: String,
+    /// For a duplicate copy, the copy kept in its place
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keeper: Option<Keeper>,
+}
+
+/// The copy a duplicate is removed in favour of, as it was when the plan was made
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keeper {
+    pub path: PathBuf,
+    /// BLAKE3 hash of the content
+    pub hash: String,
+}
+
+impl PlanEntry {
+    /// Record the file as it is now
+    pub fn new(path: &Path) -> Result<PlanEntry> {
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Failed to read metadata for file: {:?}", path))?;
+        Ok(PlanEntry {
+            path: std::path::absolute(path).unwrap_or(path.to_path_buf()),
+            size: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            inode: inode(&metadata),
+            hash: full_hash(path)?.to_hex().to_string(),
+            keeper: None,
+        })
+    }
+
+    /// Record a duplicate copy along with the copy kept in its place, the copy is only removed
+    /// while its keeper is still as planned
+    pub fn duplicate(path: &Path, keeper: &Path) -> Result<PlanEntry> {
+        Ok(PlanEntry {
+            keeper: Some(Keeper {
+                path: std::path::absolute(keeper).unwrap_or(keeper.to_path_buf()),
+                hash: full_hash(keeper)?.to_hex().to_string(),
+            }),
+            ..PlanEntry::new(path)?
+        })
+    }
+
+    /// The metadata of the file while it's still as planned, otherwise why it isn't
+    pub fn check(&self) -> std::result::Result<fs::Metadata, String> {
+        let metadata = fs::metadata(&self.path).map_err(|err| format!("can't be read: {}", err))?;
+        if !metadata.is_file() {
+            return Err("isn't a file anymore".to_string());
+        }
+        if inode(&metadata) != self.inode {
+            return Err("was replaced since planning".to_string());
+        }
+        if metadata.len() != self.size {
+            return Err(format!(
+                "size changed since planning, {} bytes instead of {}",
+                metadata.len(),
+                self.size
+            ));
+        }
+        if metadata.modified().ok().map(DateTime::<Utc>::from) != self.modified {
+            return Err("was modified since planning".to_string());
+        }
+        match full_hash(&self.path) {
+            Ok(hash) if hash.to_hex().as_str() == self.hash => {}
+            Ok(_) => return Err("content changed since planning".to_string()),
+            Err(err) => return Err(format!("{:#}", err)),
+        }
+        if let Some(keeper) = &self.keeper {
+            match full_hash(&keeper.path) {
+                Ok(hash) if hash.to_hex().as_str() == keeper.hash => {}
+                Ok(_) => {
+                    return Err(format!(
+                        "its keeper {} changed since planning",
+                        keeper.path.display()
+                    ))
+                }
+                Err(_) => return Err(format!("its keeper {} is gone", keeper.path.display())),
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+/// What a run would do, written for review and then applied exactly as it is: files that
+/// changed since planning are left alone, and files that weren't planned are never touched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub created_at: DateTime<Utc>,
+    pub root: PathBuf,
+    pub action: Action,
+    /// The filters the files were selected by, in the expression language
+    pub filters: Vec<String>,
+    pub entries: Vec<PlanEntry>,
+}
+
+/// Outcome of applying a plan
+#[derive(Debug)]
+pub struct Applied {
+    pub report: Report,
+    /// Entries left alone because their file changed since planning, with the reason
+    pub changed: Vec<(PlanEntry, String)>,
+}
+
+impl Plan {
+    /// Plan made now, with absolute paths so it can be applied from anywhere
+    pub fn new(
+        root: &Path,
+        action: &Action,
+        filters: Vec<String>,
+        entries: Vec<PlanEntry>,
+    ) -> Plan {
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or(path.to_path_buf());
+        let action = match action {
+            Action::Quarantine(quarantine) => Action::Quarantine(Quarantine {
+                root: absolute(&quarantine.root),
+                ..quarantine.clone()
+            }),
+            Action::Archive(archive) => Action::Archive(Archive {
+                path: absolute(&archive.path),
+                ..archive.clone()
+            }),
+            action => action.clone(),
+        };
+        Plan {
+            created_at: Utc::now(),
+            root: absolute(root),
+            action,
+            filters,
+            entries,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write plan: {:?}", path))
+    }
+
+    pub fn read(path: &Path) -> Result<Plan> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read plan: {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid plan: {:?}", path))
+    }
+
+    /// Apply the action to every entry whose file is still as planned. Unless it's a dry run,
+    /// what was done is written to the journal directory when one is given, even when applying
+    /// fails partway.
+    pub fn apply(&self, journal: Option<&Path>, dry_run: bool) -> Result<Applied> {
+        let start = Instant::now();
+        let started_at = Utc::now();
+        let mut report = Report::new();
+        report.action = self.action.clone();
+        report.dry_run = dry_run;
+        report.root = self.root.clone();
+
+        let mut changed = vec![];
+        let mut records = vec![];
+        for entry in &self.entries {
+            match entry.check() {
+                Ok(metadata) => records.push(FileRecord {
+                    path: entry.path.clone(),
+                    relative_path: entry
+                        .path
+                        .strip_prefix(&self.root)
+                        .unwrap_or(&entry.path)
+                        .to_path_buf(),
+                    metadata,
+                    git_ignored: false,
+                }),
+                Err(reason) => changed.push((entry.clone(), reason)),
+            }
+        }
+
+        let accepted: Vec<bool> = records.iter().map(|r| self.action.accepts(r)).collect();
+        let targets: Vec<&FileRecord> = records
+            .iter()
+            .zip(&accepted)
+            .filter_map(|(record, accepted)| accepted.then_some(record))
+            .collect();
+        let applied = apply_action(&self.action, &targets, dry_run, &mut report);
+        let applied = journal_run(
+            applied,
+            journal.filter(|_| !dry_run),
+            started_at,
+            &self.root,
+            self.filters.clone(),
+            &mut report,
+        )?;
+        let mut errors = applied.into_iter();
+        for (record, accepted) in records.into_iter().zip(accepted) {
+            let mut decision = Decision::new(
+                &record,
+                accepted,
+                match accepted {
+                    true => "planned",
+                    false => "planned, but the action can't be applied to it",
+                },
+            );
+            decision.filters = self.filters.clone();
+            if accepted {
+                decision.error = errors.next().flatten();
+                if decision.error.is_none() {
+                    decision.action = Some(self.action.name().to_string());
+                }
+            }
+            record_decision(&mut report, record, decision);
+        }
+        report.total_time_sec = start.elapsed().as_secs();
+
+        Ok(Applied { report, changed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_changed_entries_are_left_alone() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let unchanged = temp_dir.path().join("unchanged.log");
+        let edited = temp_dir.path().join("edited.log");
+        let replaced = temp_dir.path().join("replaced.log");
+        let removed = temp_dir.path().join("removed.log");
+        for path in [&unchanged, &edited, &replaced, &removed] {
+            fs::write(path, "content")?;
+        }
+        let entries = [&unchanged, &edited, &replaced, &removed]
+            .into_iter()
+            .map(|path| PlanEntry::new(path))
+            .collect::<Result<Vec<_>>>()?;
+        let plan = Plan::new(temp_dir.path(), &Action::Delete, vec![], entries);
+        let plan_file = temp_dir.path().join("plan.json");
+        plan.write(&plan_file)?;
+
+        // same size and modification time, only the content tells it apart
+        let modified = fs::metadata(&edited)?.modified()?;
+        fs::write(&edited, "CONTENT")?;
+        filetime::set_file_mtime(&edited, filetime::FileTime::from_system_time(modified))?;
+        fs::remove_file(&replaced)?;
+        fs::write(temp_dir.path().join("new.log"), "content")?;
+        fs::write(&replaced, "content")?;
+        fs::remove_file(&removed)?;
+
+        let plan = Plan::read(&plan_file)?;
+        let applied = plan.apply(None, true)?;
+        assert!(unchanged.exists());
+        assert_eq!(applied.report.files_deleted, 1);
+
+        let applied = plan.apply(None, false)?;
+        assert!(!unchanged.exists());
+        assert!(edited.exists());
+        assert!(replaced.exists());
+        assert_eq!(applied.report.files_deleted, 1);
+        assert_eq!(applied.changed.len(), 3);
+        assert!(applied.changed[0].1.contains("content changed"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,468 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::*;
+use filetime::{set_file_mtime, FileTime};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use super::cleaner_file_duplicate::full_hash;
+use super::journal::new_run_id;
+use super::path_matcher::PathMatcher;
+use super::scanner::FileRecord;
+use super::utils::absolute_path;
+
+/// Name of the manifest at the quarantine root, one JSON entry per line
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+
+/// Stages files in a quarantine root instead of deleting them. Every run moves its files into
+/// its own directory under the root, keeping their layout relative to the cleaned directory,
+/// and records each file in the manifest so it can be restored or purged later.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quarantine {
+    pub root: PathBuf,
+    /// Name of this run's directory under the root, a new one whenever the action is read back
+    #[serde(skip_serializing, default = "new_run_id")]
+    pub run: String,
+}
+
+/// A quarantined file as recorded in the manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub run: String,
+    pub original_path: PathBuf,
+    /// Where the file is now, relative to the quarantine root
+    pub quarantined_path: PathBuf,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// BLAKE3 hash of the content, checked when the file is restored
+    pub hash: String,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+impl Quarantine {
+    /// Quarantine into `root`, in a new run directory named after the current time
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Quarantine {
+            root: root.into(),
+            run: new_run_id(),
+        }
+    }
+
+    /// Move a file into the quarantine and record it in the manifest, returning where it went
+    /// relative to the root
+    pub fn quarantine_file(&self, record: &FileRecord, dry_run: bool) -> Result<Option<PathBuf>> {
+        let path = &record.path;
+        if dry_run {
+            if let Some(pth) = path.to_str() {
+                eprintln!("\n {} could have been quarantined", pth.bold().yellow());
+            }
+            return Ok(None);
+        }
+
+        let relative = match record.relative_path.file_name() {
+            Some(_) => record.relative_path.clone(),
+            None => PathBuf::from(path.file_name().unwrap_or(path.as_os_str())),
+        };
+        let quarantined_path = Path::new(&self.run).join(relative);
+        let entry = ManifestEntry {
+            run: self.run.clone(),
+            original_path: absolute_path(path).unwrap_or_else(|_| path.clone()),
+            quarantined_path: quarantined_path.clone(),
+            size: record.metadata.len(),
+            modified: record.metadata.modified().ok().map(DateTime::<Utc>::from),
+            hash: full_hash(path)?.to_hex().to_string(),
+            quarantined_at: Utc::now(),
+        };
+
+        let destination = self.root.join(&quarantined_path);
+        move_file(path, &destination)
+            .with_context(|| format!("Failed to quarantine file: {:?}", path))?;
+        // a file missing from the manifest could never be restored, so it's put back
+        if let Err(err) = append_entry(&self.root, &entry) {
+            return Err(match move_file(&destination, path) {
+                Ok(()) => err.context(format!("Failed to quarantine file: {:?}", path)),
+                Err(undo) => err.context(format!(
+                    "Failed to quarantine file: {:?}, it was left at {:?}: {:#}",
+                    path, destination, undo
+                )),
+            });
+        }
+        Ok(Some(quarantined_path))
+    }
+}
+
+/// Move a file, copying it when the destination is on another filesystem
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(to).is_ok() {
+        anyhow::bail!("Destination already exists: {:?}", to);
+    }
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+            let modified = fs::metadata(from)?.modified()?;
+            fs::copy(from, to)?;
+            set_file_mtime(to, FileTime::from_system_time(modified))?;
+            fs::remove_file(from)?;
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn append_entry(root: &Path, entry: &ManifestEntry) -> Result<()> {
+    let manifest = root.join(MANIFEST_FILE);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&manifest)
+        .with_context(|| format!("Failed to open quarantine manifest: {:?}", manifest))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Read every entry of the manifest at the quarantine root
+pub fn read_manifest(root: &Path) -> Result<Vec<ManifestEntry>> {
+    let manifest = root.join(MANIFEST_FILE);
+    let file = match fs::File::open(&manifest) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Failed to open quarantine manifest: {:?}", manifest))
+        }
+    };
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(
+            serde_json::from_str(&line)
+                .with_context(|| format!("Invalid quarantine manifest entry: {}", line))?,
+        );
+    }
+    Ok(entries)
+}
+
+/// Replace the manifest with the given entries
+fn write_manifest(root: &Path, entries: &[ManifestEntry]) -> Result<()> {
+    let manifest = root.join(MANIFEST_FILE);
+    let temp = root.join(format!("{}.tmp", MANIFEST_FILE));
+    let mut file = fs::File::create(&temp)?;
+    for entry in entries {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    file.sync_all()?;
+    fs::rename(&temp, &manifest)?;
+    Ok(())
+}
+
+/// Which quarantined files to restore
+#[derive(Debug, Clone, Default)]
+pub struct RestoreSelection {
+    /// Only files from this run
+    pub run: Option<String>,
+    /// Only files whose original path matches one of these patterns, see [`PathMatcher`]
+    pub patterns: Vec<String>,
+}
+
+/// Put quarantined files back where they came from. Files whose original path is taken again
+/// stay in quarantine. Returns the restored entries.
+pub fn restore(
+    root: &Path,
+    selection: &RestoreSelection,
+    dry_run: bool,
+) -> Result<Vec<ManifestEntry>> {
+    let matcher = PathMatcher::new(&selection.patterns, Some(Path::new("/")))?;
+    restore_where(root, dry_run, |entry| {
+        selection.run.as_ref().is_none_or(|run| *run == entry.run)
+            && (selection.patterns.is_empty() || {
+                let original = &entry.original_path;
+                matcher.is_match(original.strip_prefix("/").unwrap_or(original), false)
+            })
+    })
+}
+
+/// Restore the files quarantined at these paths, relative to the root
+pub fn restore_paths(
+    root: &Path,
+    quarantined_paths: &[PathBuf],
+    dry_run: bool,
+) -> Result<Vec<ManifestEntry>> {
+    restore_where(root, dry_run, |entry| {
+        quarantined_paths.contains(&entry.quarantined_path)
+    })
+}
+
+fn restore_where(
+    root: &Path,
+    dry_run: bool,
+    selected: impl Fn(&ManifestEntry) -> bool,
+) -> Result<Vec<ManifestEntry>> {
+    let mut kept = vec![];
+    let mut restored = vec![];
+
+    for entry in read_manifest(root)? {
+        if !selected(&entry) {
+            kept.push(entry);
+            continue;
+        }
+
+        let source = root.join(&entry.quarantined_path);
+        if dry_run {
+            eprintln!(
+                "\n {} could have been restored",
+                entry.original_path.display().to_string().bold().yellow()
+            );
+            restored.push(entry);
+            continue;
+        }
+        if fs::symlink_metadata(&entry.original_path).is_ok() {
+            eprintln!(
+                "Not restoring {:?}, the path exists again, keeping it in quarantine",
+                entry.original_path
+            );
+            kept.push(entry);
+            continue;
+        }
+        match full_hash(&source) {
+            Ok(hash) if hash.to_hex().as_str() != entry.hash => {
+                eprintln!(
+                    "Content of {:?} changed while in quarantine, restoring it anyway",
+                    source
+                );
+            }
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!(
+                    "Could not restore file, keeping it in quarantine: {:#}",
+                    err
+                );
+                kept.push(entry);
+                continue;
+            }
+        }
+        if let Err(err) = move_file(&source, &entry.original_path) {
+            eprintln!(
+                "Could not restore {:?}, keeping it in quarantine: {:#}",
+                entry.original_path, err
+            );
+            kept.push(entry);
+            continue;
+        }
+        restored.push(entry);
+    }
+
+    if !dry_run {
+        // the restored files are back whether or not the manifest can be updated
+        if let Err(err) = write_manifest(root, &kept) {
+            eprintln!(
+                "Could not update the manifest in {:?}, proceeding: {:#}",
+                root, err
+            );
+        }
+        remove_empty_runs(root, &restored);
+    }
+    Ok(restored)
+}
+
+/// Permanently delete quarantined files, all of them or those quarantined before the cutoff.
+/// Returns the purged entries.
+pub fn purge(
+    root: &Path,
+    older_than: Option<DateTime<Utc>>,
+    dry_run: bool,
+) -> Result<Vec<ManifestEntry>> {
+    let mut kept = vec![];
+    let mut purged = vec![];
+
+    for entry in read_manifest(root)? {
+        if older_than.is_some_and(|cutoff| entry.quarantined_at >= cutoff) {
+            kept.push(entry);
+            continue;
+        }
+        let path = root.join(&entry.quarantined_path);
+        if dry_run {
+            eprintln!(
+                "\n {} could have been purged",
+                path.display().to_string().bold().yellow()
+            );
+        } else {
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => {
+                    eprintln!("Could not purge {:?}, proceeding: {}", path, err);
+                    kept.push(entry);
+                    continue;
+                }
+            }
+        }
+        purged.push(entry);
+    }
+
+    if !dry_run {
+        write_manifest(root, &kept)?;
+        remove_empty_runs(root, &purged);
+    }
+    Ok(purged)
+}
+
+/// Remove the directories left empty by the given entries, up to their run directory
+fn remove_empty_runs(root: &Path, entries: &[ManifestEntry]) {
+    for entry in entries {
+        let mut dir = entry.quarantined_path.parent();
+        while let Some(relative) = dir.filter(|d| !d.as_os_str().is_empty()) {
+            // fails once a directory still holds files
+            if fs::remove_dir(root.join(relative)).is_err() {
+                break;
+            }
+            dir = relative.parent();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn record(root: &Path, relative: &str) -> Result<FileRecord> {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, relative)?;
+        Ok(FileRecord {
+            metadata: fs::metadata(&path)?,
+            path,
+            relative_path: PathBuf::from(relative),
+            git_ignored: false,
+        })
+    }
+
+    #[test]
+    fn test_quarantine_keeps_the_layout_and_restores() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let data = temp_dir.path().join("data");
+        let root = temp_dir.path().join("quarantine");
+        let quarantine = Quarantine::new(&root);
+
+        let log = record(&data, "logs/app.log")?;
+        let tmp = record(&data, "cache/x.tmp")?;
+        quarantine.quarantine_file(&log, false)?;
+        quarantine.quarantine_file(&tmp, false)?;
+
+        assert!(!log.path.exists());
+        assert!(root.join(&quarantine.run).join("logs/app.log").exists());
+        let entries = read_manifest(&root)?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].original_path,
+            fs::canonicalize(&data)?.join("logs/app.log")
+        );
+        assert_eq!(entries[0].size, "logs/app.log".len() as u64);
+        assert_eq!(
+            entries[0].hash,
+            blake3::hash(b"logs/app.log").to_hex().to_string()
+        );
+
+        let selection = RestoreSelection {
+            patterns: vec!["*.log".to_string()],
+            ..Default::default()
+        };
+        let restored = restore(&root, &selection, false)?;
+        assert_eq!(restored.len(), 1);
+        assert_eq!(fs::read_to_string(&log.path)?, "logs/app.log");
+        assert!(!tmp.path.exists());
+        assert_eq!(read_manifest(&root)?.len(), 1);
+        assert!(!root.join(&quarantine.run).join("logs").exists());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_quarantined_symlink_records_the_link() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let data = temp_dir.path().join("data");
+        let root = temp_dir.path().join("quarantine");
+        let target = record(temp_dir.path(), "target.txt")?;
+        let link = data.join("link.txt");
+        fs::create_dir_all(&data)?;
+        std::os::unix::fs::symlink(&target.path, &link)?;
+        let file = FileRecord {
+            metadata: fs::symlink_metadata(&link)?,
+            path: link.clone(),
+            relative_path: PathBuf::from("link.txt"),
+            git_ignored: false,
+        };
+
+        Quarantine::new(&root).quarantine_file(&file, false)?;
+        assert_eq!(
+            read_manifest(&root)?[0].original_path,
+            fs::canonicalize(&data)?.join("link.txt")
+        );
+        assert!(target.path.exists());
+
+        restore(&root, &RestoreSelection::default(), false)?;
+        assert!(fs::symlink_metadata(&link)?.file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target.path)?, "target.txt");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_is_put_back_when_it_cannot_be_recorded() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path().join("quarantine");
+        // the manifest can't be opened for appending
+        fs::create_dir_all(root.join(MANIFEST_FILE))?;
+        let file = record(temp_dir.path(), "notes.txt")?;
+
+        assert!(Quarantine::new(&root)
+            .quarantine_file(&file, false)
+            .is_err());
+        assert!(file.path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_never_overwrites() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path().join("quarantine");
+        let file = record(temp_dir.path(), "notes.txt")?;
+        Quarantine::new(&root).quarantine_file(&file, false)?;
+        fs::write(&file.path, "new content")?;
+
+        assert!(restore(&root, &RestoreSelection::default(), false)?.is_empty());
+        assert_eq!(fs::read_to_string(&file.path)?, "new content");
+        assert_eq!(read_manifest(&root)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_purge_older_than() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path().join("quarantine");
+        let quarantine = Quarantine::new(&root);
+        let file = record(temp_dir.path(), "old.log")?;
+        quarantine.quarantine_file(&file, false)?;
+
+        let cutoff = Utc::now() - chrono::Duration::days(1);
+        assert!(purge(&root, Some(cutoff), false)?.is_empty());
+        assert!(root.join(&quarantine.run).join("old.log").exists());
+
+        assert_eq!(purge(&root, None, false)?.len(), 1);
+        assert!(!root.join(&quarantine.run).exists());
+        assert!(read_manifest(&root)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,649 @@
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::report::{Decision, DuplicateGroup, Report};
+
+use super::action::Action;
+use super::cleaner_file_duplicate::{find_duplicate_sets, link_duplicate, DedupAction};
+use super::filter::FilterContext;
+use super::filter_expression::Expr;
+use super::ignore_files::IgnoreFiles;
+use super::interactive::confirm;
+use super::journal::{JournalEntry, Operation};
+use super::path_matcher::PathMatcher;
+use super::tui::browse;
+use super::utils::collect_metrics;
+
+/// Everything the filters need to know about a file, read once during the walk
+#[derive(Debug)]
+pub struct FileRecord {
+    pub path: PathBuf,
+    /// Path relative to the cleaned directory, used for pattern matching
+    pub relative_path: PathBuf,
+    pub metadata: fs::Metadata,
+    /// Whether git ignores the file, only looked up when a filter asks for it
+    pub git_ignored: bool,
+}
+
+/// The enabled filters. A file is deleted when the expression selects it, nothing is deleted
+/// without an expression.
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    pub expr: Option<Expr>,
+    /// Options of the filters, such as how duplicates are found and handled when the
+    /// expression uses `dup()`. The root is always the scanned directory.
+    pub context: FilterContext,
+    /// Ask which of the selected files to act on, see [`confirm`]
+    pub interactive: bool,
+    /// Choose which of the selected files to act on in a full screen browser, see [`browse`]
+    pub tui: bool,
+}
+
+impl Filters {
+    pub fn matches(&self, record: &FileRecord, is_duplicate: bool) -> bool {
+        self.expr
+            .as_ref()
+            .is_some_and(|expr| expr.eval(record, is_duplicate))
+    }
+
+    /// The predicates deciding whether the file matches, `None` without an expression
+    pub fn explain(&self, record: &FileRecord, is_duplicate: bool) -> Option<Vec<String>> {
+        self.expr
+            .as_ref()
+            .map(|expr| expr.explain(record, is_duplicate))
+    }
+
+    /// Add a filter that must hold as well as the existing ones
+    pub fn and(&mut self, expr: Expr) {
+        self.expr = Some(match self.expr.take() {
+            Some(existing) => existing.and(expr),
+            None => expr,
+        });
+    }
+}
+
+/// Walk the directory once, reading the metadata of every file. Directories matching the
+/// ignore patterns or protected by a `.cleanerignore` are not walked at all.
+pub fn walk_directory(
+    directory: &String,
+    ignore: &PathMatcher,
+    ignore_files: &mut IgnoreFiles,
+) -> Result<Vec<FileRecord>> {
+    let root = Path::new(directory);
+    let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+    let ignore_files = RefCell::new(ignore_files);
+    let mut records = vec![];
+
+    let walker = WalkDir::new(directory).into_iter().filter_entry(|entry| {
+        let skip = entry.file_type().is_dir() && entry.depth() > 0 && {
+            let relative_path = relative(entry.path());
+            ignore.is_match(&relative_path, true)
+                || ignore_files.borrow_mut().is_protected(&relative_path, true)
+        };
+        if skip {
+            eprintln!("Skipping ignored path: {:?}", entry.path());
+        }
+        !skip
+    });
+    for entry in walker.filter_map(|f| {
+        match f {
+            Ok(entry) => Some(entry), // Return valid entries
+            Err(err) => {
+                eprintln!(
+                    "Error accessing file: {}, Error: {}",
+                    err.path().unwrap_or_else(|| Path::new("unknown")).display(),
+                    err
+                );
+                None // Skip erroneous entries
+            }
+        }
+    }) {
+        let path = entry.path();
+
+        if path.is_file() {
+            // a symlink is a file of its own, its target is walked (or not) on its own
+            let metadata = fs::symlink_metadata(path)
+                .with_context(|| format!("Failed to read metadata for file: {:?}", path))?;
+            let relative_path = relative(path);
+            let git_ignored = ignore_files
+                .borrow_mut()
+                .is_git_ignored(&relative_path, false);
+            records.push(FileRecord {
+                path: path.to_path_buf(),
+                relative_path,
+                metadata,
+                git_ignored,
+            });
+        }
+    }
+
+    Ok(records)
+}
+
+/// Walk the directory once and run every enabled filter against each file, so every file gets
+/// a single decision and is counted once in the report
+pub fn scan_directory(
+    directory: &String,
+    filters: &Filters,
+    action: &Action,
+    dry_run: bool,
+    report_data: &mut Report,
+    paths_to_ignore: &[String],
+) -> Result<()> {
+    report_data.action = action.clone();
+    report_data.dry_run = dry_run;
+    report_data.root = PathBuf::from(directory);
+    let ignore = PathMatcher::new(paths_to_ignore, Some(Path::new(directory)))?;
+    let read_git_files = filters.expr.as_ref().is_some_and(|e| e.uses_git_ignores());
+    let mut ignore_files = IgnoreFiles::new(Path::new(directory), read_git_files);
+    let records = walk_directory(directory, &ignore, &mut ignore_files)?;
+    let protected: Vec<bool> = records
+        .iter()
+        .map(|r| {
+            let protected = ignore.is_match(&r.relative_path, false)
+                || ignore_files.is_protected(&r.relative_path, false);
+            if protected {
+                eprintln!("Skipping ignored file: {:?}", r.path);
+            }
+            protected
+        })
+        .collect();
+
+    let mut is_duplicate = vec![false; records.len()];
+    let mut duplicate_sets = vec![];
+
+    if let Some(expr) = &filters.expr {
+        expr.prepare(&records)?;
+        let context = FilterContext {
+            root: PathBuf::from(directory),
+            ..filters.context.clone()
+        };
+        let eligible: Vec<bool> = protected.iter().map(|p| !p).collect();
+        duplicate_sets = find_duplicate_sets(expr, &records, &eligible, &context)?;
+        for set in &duplicate_sets {
+            for idx in &set.copies {
+                is_duplicate[*idx] = true;
+            }
+        }
+    }
+
+    let mut reasons = Vec::with_capacity(records.len());
+    let mut matched = vec![vec![]; records.len()];
+    let mut selected: Vec<bool> = records
+        .iter()
+        .enumerate()
+        .map(|(idx, record)| {
+            if protected[idx] {
+                reasons.push("protected by an ignore pattern or ignore file".to_string());
+                return false;
+            }
+            let explained = filters.explain(record, is_duplicate[idx]);
+            if !filters.matches(record, is_duplicate[idx]) {
+                reasons.push(match explained {
+                    Some(explained) => format!("didn't match {}", explained.join(", ")),
+                    None => "no filter given".to_string(),
+                });
+                return false;
+            }
+            matched[idx] = explained.unwrap_or_default();
+            let explained = matched[idx].join(", ");
+            if !action.accepts(record) {
+                reasons.push(format!(
+                    "matched {}, but the action can't be applied to it",
+                    explained
+                ));
+                return false;
+            }
+            reasons.push(format!("matched {}", explained));
+            true
+        })
+        .collect();
+    let chosen = selected.clone();
+    if filters.tui {
+        browse(&records, &mut selected, directory)?;
+    } else if filters.interactive {
+        confirm(
+            &records,
+            &mut selected,
+            &duplicate_sets,
+            &mut io::stdin().lock(),
+        )?;
+    }
+    for (idx, reason) in reasons.iter_mut().enumerate() {
+        if chosen[idx] && !selected[idx] {
+            reason.push_str(", but wasn't confirmed");
+        }
+    }
+    let mut linked = vec![None; records.len()];
+
+    for set in duplicate_sets {
+        let mut group = DuplicateGroup {
+            keeper: records[set.keeper].path.display().to_string(),
+            removed: vec![],
+            linked: vec![],
+        };
+        for idx in set.copies {
+            if !selected[idx] {
+                continue;
+            }
+            let copy = records[idx].path.display().to_string();
+            if set.action == DedupAction::Delete {
+                group.removed.push(copy);
+                continue;
+            }
+            // linked copies stay in place instead of being deleted
+            selected[idx] = false;
+            let keeper = &records[set.keeper].path;
+            if let Some(kind) = link_duplicate(keeper, &records[idx].path, set.action, dry_run) {
+                linked[idx] = Some(kind);
+                group.linked.push(copy);
+                if !dry_run {
+                    let keeper = keeper.clone();
+                    report_data.journal.push(JournalEntry {
+                        path: records[idx].path.clone(),
+                        size: records[idx].metadata.len(),
+                        operation: match kind {
+                            DedupAction::Reflink => Operation::Reflink { keeper },
+                            _ => Operation::Hardlink { keeper },
+                        },
+                    });
+                }
+            }
+        }
+        if !group.removed.is_empty() || !group.linked.is_empty() {
+            report_data.duplicate_groups.push(group);
+        }
+    }
+
+    let indices: Vec<usize> = (0..records.len()).filter(|idx| selected[*idx]).collect();
+    let targets: Vec<&FileRecord> = indices.iter().map(|idx| &records[*idx]).collect();
+    let mut errors = vec![None; records.len()];
+    for (idx, error) in
+        indices
+            .into_iter()
+            .zip(apply_action(action, &targets, dry_run, report_data)?)
+    {
+        errors[idx] = error;
+    }
+
+    for (idx, record) in records.into_iter().enumerate() {
+        let mut decision = Decision::new(&record, selected[idx], std::mem::take(&mut reasons[idx]));
+        decision.error = errors[idx].take();
+        decision.filters = std::mem::take(&mut matched[idx]);
+        if let Some(kind) = linked[idx] {
+            report_data.files_linked += 1;
+            report_data.total_file_size_linked += record.metadata.len();
+            decision.action = Some(match kind {
+                DedupAction::Reflink => "reflink".to_string(),
+                _ => "hardlink".to_string(),
+            });
+            decision.reason.push_str(", linked to its keeper");
+        }
+        if decision.selected && decision.error.is_none() {
+            decision.action = Some(action.name().to_string());
+        }
+        record_decision(report_data, record, decision);
+    }
+
+    Ok(())
+}
+
+/// Apply the action to the selected files, recording them and, unless it's a dry run, what was
+/// done to each in the report. Returns why the action failed for each file it failed for,
+/// the others are still acted on.
+pub fn apply_action(
+    action: &Action,
+    targets: &[&FileRecord],
+    dry_run: bool,
+    report_data: &mut Report,
+) -> Result<Vec<Option<String>>> {
+    let operations = action.apply(targets, dry_run)?;
+    let mut errors = vec![];
+    for (record, operation) in targets.iter().zip(operations) {
+        let operation = match operation {
+            Ok(operation) => operation,
+            Err(err) => {
+                eprintln!("{:#}, proceeding", err);
+                errors.push(Some(format!("{:#}", err)));
+                continue;
+            }
+        };
+        errors.push(None);
+        report_data.targets.push(record.path.clone());
+        if let Some(Operation::Compress {
+            compressed_size, ..
+        }) = &operation
+        {
+            report_data.total_file_size_saved +=
+                record.metadata.len().saturating_sub(*compressed_size);
+        }
+        if let (Some(operation), false) = (operation, dry_run) {
+            report_data.journal.push(JournalEntry {
+                path: record.path.clone(),
+                size: record.metadata.len(),
+                operation,
+            });
+        }
+    }
+    Ok(errors)
+}
+
+/// Count the file in the report, as acted on when it was selected and the action didn't fail,
+/// and record what was decided for it
+pub fn record_decision(report_data: &mut Report, record: FileRecord, decision: Decision) {
+    let size = record.metadata.len();
+    let del_meta = if decision.selected && decision.error.is_none() {
+        (1, size)
+    } else {
+        (0, 0)
+    };
+    report_data.decisions.push(decision);
+    collect_metrics(report_data, record.metadata, &record.path, del_meta);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::filter_expression::parse_expression;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_file_matched_by_several_filters_is_counted_once() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let log = temp_dir.path().join("app.log");
+        let txt = temp_dir.path().join("notes.txt");
+        File::create(&log)?.set_len(4000)?;
+        File::create(&txt)?.set_len(100)?;
+
+        let filters = Filters {
+            expr: Some(parse_expression("ext(log) or size>=2000")?),
+            ..Default::default()
+        };
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        scan_directory(&dir_str, &filters, &Action::Delete, false, &mut report, &[])?;
+
+        assert!(!log.exists());
+        assert!(txt.exists());
+        assert_eq!(report.files_scanned, 2);
+        assert_eq!(report.files_deleted, 1);
+        assert_eq!(report.total_file_size_deleted, 4000);
+        assert_eq!(report.total_file_size_retained, 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignored_files_are_not_reported_as_deleted() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let keep = temp_dir.path().join("keep.log");
+        File::create(&keep)?.set_len(10)?;
+
+        let filters = Filters {
+            expr: Some(Expr::Ext(vec!["log".to_string()])),
+            ..Default::default()
+        };
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        scan_directory(
+            &dir_str,
+            &filters,
+            &Action::Delete,
+            false,
+            &mut report,
+            &["keep.log".to_string()],
+        )?;
+
+        assert!(keep.exists());
+        assert_eq!(report.files_deleted, 0);
+        assert_eq!(report.total_files_retained, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_patterns_protect_files_and_directories() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let build = temp_dir.path().join("build");
+        let nested = temp_dir.path().join("src/build");
+        fs::create_dir_all(&build)?;
+        fs::create_dir_all(&nested)?;
+        let kept_object = build.join("main.o");
+        let removed_object = nested.join("main.o");
+        let kept_log = temp_dir.path().join("keep-2024.log");
+        let removed_log = temp_dir.path().join("app.log");
+        for path in [&kept_object, &removed_object, &kept_log, &removed_log] {
+            fs::write(path, "content")?;
+        }
+
+        let filters = Filters {
+            expr: Some(parse_expression("ext(o,log)")?),
+            ..Default::default()
+        };
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        scan_directory(
+            &dir_str,
+            &filters,
+            &Action::Delete,
+            false,
+            &mut report,
+            &["/build".to_string(), r"re:keep-\d+\.log$".to_string()],
+        )?;
+
+        assert!(kept_object.exists());
+        assert!(!removed_object.exists());
+        assert!(kept_log.exists());
+        assert!(!removed_log.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_filters_combine_with_and() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let big_log = temp_dir.path().join("big.log");
+        let small_log = temp_dir.path().join("small.log");
+        let big_txt = temp_dir.path().join("big.txt");
+        File::create(&big_log)?.set_len(2000)?;
+        File::create(&small_log)?.set_len(10)?;
+        File::create(&big_txt)?.set_len(2000)?;
+
+        let mut filters = Filters::default();
+        filters.and(Expr::Ext(vec!["log".to_string()]));
+        filters.and(parse_expression("size>=1000")?);
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        scan_directory(&dir_str, &filters, &Action::Delete, false, &mut report, &[])?;
+
+        assert!(!big_log.exists());
+        assert!(small_log.exists());
+        assert!(big_txt.exists());
+        assert_eq!(report.files_deleted, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicates_of_deleted_keeper_are_kept() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let log = temp_dir.path().join("a.log");
+        let txt = temp_dir.path().join("b.txt");
+        fs::write(&log, "same")?;
+        fs::write(&txt, "same")?;
+
+        let filters = Filters {
+            expr: Some(parse_expression("dup() or ext(log)")?),
+            context: FilterContext {
+                options: [("keep".to_string(), vec!["alpha".to_string()])].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        scan_directory(&dir_str, &filters, &Action::Delete, false, &mut report, &[])?;
+
+        assert!(!log.exists());
+        assert!(txt.exists(), "The last copy must never be removed");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanerignore_protects_paths() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let vendor = temp_dir.path().join("vendor");
+        fs::create_dir_all(&vendor)?;
+        let kept = vendor.join("lib.log");
+        let removed = temp_dir.path().join("app.log");
+        fs::write(&kept, "content")?;
+        fs::write(&removed, "content")?;
+        fs::write(temp_dir.path().join(".cleanerignore"), "vendor/\n")?;
+
+        let filters = Filters {
+            expr: Some(parse_expression("ext(log) or name(.cleanerignore)")?),
+            ..Default::default()
+        };
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        scan_directory(&dir_str, &filters, &Action::Delete, false, &mut report, &[])?;
+
+        assert!(kept.exists());
+        assert!(!removed.exists());
+        assert!(temp_dir.path().join(".cleanerignore").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_git_ignored_files_are_selected() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let target = temp_dir.path().join("target");
+        fs::create_dir_all(&target)?;
+        let build_output = target.join("app");
+        let source = temp_dir.path().join("main.rs");
+        fs::write(&build_output, "binary")?;
+        fs::write(&source, "fn main() {}")?;
+        fs::write(temp_dir.path().join(".gitignore"), "/target\n")?;
+
+        let filters = Filters {
+            expr: Some(parse_expression("ignored()")?),
+            ..Default::default()
+        };
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        scan_directory(&dir_str, &filters, &Action::Delete, false, &mut report, &[])?;
+
+        assert!(!build_output.exists());
+        assert!(source.exists());
+        assert!(temp_dir.path().join(".gitignore").exists());
+
+        Ok(())
+    }
+
+    /// Selects the largest file, which needs to see every file first
+    #[derive(Debug, Default)]
+    struct Largest(std::sync::Mutex<u64>);
+
+    impl crate::features::filter::Predicate for Largest {
+        fn matches(&self, record: &FileRecord) -> bool {
+            record.metadata.len() == *self.0.lock().unwrap()
+        }
+
+        fn prepare(&self, records: &[FileRecord]) -> Result<()> {
+            *self.0.lock().unwrap() = records.iter().map(|r| r.metadata.len()).max().unwrap_or(0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_custom_predicates_are_prepared_before_matching() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let big = temp_dir.path().join("big.bin");
+        let small = temp_dir.path().join("small.bin");
+        File::create(&big)?.set_len(2000)?;
+        File::create(&small)?.set_len(10)?;
+
+        let filters = Filters {
+            expr: Some(Expr::Custom(std::sync::Arc::new(Largest::default()))),
+            ..Default::default()
+        };
+        let mut report = Report::new();
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        scan_directory(&dir_str, &filters, &Action::Delete, false, &mut report, &[])?;
+
+        assert!(!big.exists());
+        assert!(small.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decisions_explain_each_file() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = temp_dir.path().join("root");
+        fs::create_dir_all(&root)?;
+        for name in ["app.log", "notes.txt", "keep.log"] {
+            fs::write(root.join(name), "content")?;
+        }
+        // a quarantine root that can't be created fails every file moved into it
+        let blocked = temp_dir.path().join("blocked");
+        fs::write(&blocked, "a file")?;
+
+        let filters = Filters {
+            expr: Some(parse_expression("ext(log)")?),
+            ..Default::default()
+        };
+        let mut report = Report::new();
+        let dir_str = root.to_str().unwrap().to_string();
+        scan_directory(
+            &dir_str,
+            &filters,
+            &Action::Quarantine(crate::features::quarantine::Quarantine::new(&blocked)),
+            false,
+            &mut report,
+            &["keep.log".to_string()],
+        )?;
+
+        let decision = |name: &str| {
+            report
+                .decisions
+                .iter()
+                .find(|d| d.path.ends_with(name))
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(decision("notes.txt").reason, "didn't match ext(log)");
+        assert!(!decision("notes.txt").selected);
+        assert_eq!(
+            decision("keep.log").reason,
+            "protected by an ignore pattern or ignore file"
+        );
+        let failed = decision("app.log");
+        assert!(failed.selected);
+        assert_eq!(failed.reason, "matched ext(log)");
+        assert_eq!(failed.filters, ["ext(log)"]);
+        assert_eq!(failed.action, None);
+        assert!(failed.error.is_some());
+        assert!(root.join("app.log").exists());
+        assert_eq!(report.files_deleted, 0);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()?)?;
+        assert_eq!(json["schema_version"], 1);
+        assert_eq!(json["action"], "quarantine");
+        assert_eq!(json["totals"]["files_scanned"], 3);
+        assert_eq!(json["totals"]["errors"], 1);
+        assert_eq!(json["files"].as_array().unwrap().len(), 3);
+        let by_filter = &json["breakdowns"]["by_filter"][0];
+        assert_eq!(by_filter["key"], "ext(log)");
+        assert_eq!(by_filter["files_retained"], 1);
+
+        Ok(())
+    }
+}
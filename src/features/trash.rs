@@ -0,0 +1,341 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Local;
+use colored::*;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use super::utils::absolute_path;
+
+/// A trash directory as described by the freedesktop.org Trash specification: trashed files
+/// are moved to `files/`, and a `.trashinfo` file in `info/` records where they came from so
+/// the desktop can restore them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashDir {
+    pub path: PathBuf,
+    /// The directory trashed paths are recorded relative to, the mount point for the per-mount
+    /// trash directories. Paths in the home trash are absolute.
+    pub top_dir: Option<PathBuf>,
+}
+
+impl TrashDir {
+    fn files(&self) -> PathBuf {
+        self.path.join("files")
+    }
+
+    fn info(&self) -> PathBuf {
+        self.path.join("info")
+    }
+}
+
+/// The home trash, `$XDG_DATA_HOME/Trash` or `~/.local/share/Trash`
+pub fn home_trash() -> Result<TrashDir> {
+    let data_home = match env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".local").join("share"))
+            .ok_or_else(|| anyhow!("Could not find the home trash, HOME is not set"))?,
+    };
+    Ok(TrashDir {
+        path: data_home.join("Trash"),
+        top_dir: None,
+    })
+}
+
+/// Move a file to the trash, returning where it went. Files on the same filesystem as the home
+/// trash go there, others to the trash directory at the top of their own mount.
+pub fn trash_file(path: &Path, dry_run: bool) -> Result<Option<PathBuf>> {
+    if dry_run {
+        if let Some(pth) = path.to_str() {
+            eprintln!("\n {} could have been moved to trash", pth.bold().yellow());
+        }
+        return Ok(None);
+    }
+
+    // only the parent is resolved, a symlink is trashed itself rather than its target
+    let path =
+        absolute_path(path).with_context(|| format!("Failed to move file to trash: {:?}", path))?;
+    let trash = trash_dir_for(&path)?;
+    let trashed = move_to_trash(&path, &trash)
+        .with_context(|| format!("Failed to move file to trash: {:?}", path))?;
+    Ok(Some(trashed))
+}
+
+#[cfg(unix)]
+fn trash_dir_for(path: &Path) -> Result<TrashDir> {
+    use std::os::unix::fs::MetadataExt;
+
+    let home = home_trash()?;
+    let file_dev = fs::symlink_metadata(path)?.dev();
+    // the home trash may not exist yet, the closest existing parent tells its filesystem
+    let home_dev = home
+        .path
+        .ancestors()
+        .find_map(|dir| fs::metadata(dir).ok())
+        .map(|metadata| metadata.dev());
+    if home_dev == Some(file_dev) {
+        return Ok(home);
+    }
+
+    let top_dir = mount_point(path)?;
+    // SAFETY: getuid has no preconditions and can't fail
+    let uid = unsafe { libc::getuid() };
+
+    // an admin-created `$topdir/.Trash` with the sticky bit (0o1000) holds a directory per user
+    let shared = top_dir.join(".Trash");
+    if let Ok(metadata) = fs::symlink_metadata(&shared) {
+        if metadata.is_dir() && metadata.mode() & 0o1000 != 0 {
+            let user_dir = shared.join(uid.to_string());
+            if fs::create_dir_all(&user_dir).is_ok() {
+                return Ok(TrashDir {
+                    path: user_dir,
+                    top_dir: Some(top_dir),
+                });
+            }
+        }
+    }
+
+    Ok(TrashDir {
+        path: top_dir.join(format!(".Trash-{}", uid)),
+        top_dir: Some(top_dir),
+    })
+}
+
+#[cfg(not(unix))]
+fn trash_dir_for(_path: &Path) -> Result<TrashDir> {
+    home_trash()
+}
+
+/// The top directory of the mount containing the path
+#[cfg(unix)]
+fn mount_point(path: &Path) -> Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = fs::symlink_metadata(path)?.dev();
+    let mut top = path;
+    while let Some(parent) = top.parent() {
+        if fs::metadata(parent)?.dev() != dev {
+            break;
+        }
+        top = parent;
+    }
+    Ok(top.to_path_buf())
+}
+
+/// Move an absolute path into the trash directory, creating the directory if needed
+pub fn move_to_trash(path: &Path, trash: &TrashDir) -> Result<PathBuf> {
+    create_private_dir(&trash.files())?;
+    create_private_dir(&trash.info())?;
+
+    let original = match &trash.top_dir {
+        Some(top_dir) => path.strip_prefix(top_dir).unwrap_or(path),
+        None => path,
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Not a file: {:?}", path))?;
+
+    // creating the info file first reserves the name, the spec requires it to be atomic
+    for attempt in 1.. {
+        let mut trashed_name = name.to_os_string();
+        if attempt > 1 {
+            trashed_name.push(format!(".{}", attempt));
+        }
+        let info_path = trash.info().join(info_name(&trashed_name));
+        let mut info = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(info) => info,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let trashed = trash.files().join(&trashed_name);
+        if fs::symlink_metadata(&trashed).is_ok() {
+            // left over from an interrupted trash operation, never overwrite it
+            drop(info);
+            let _ = fs::remove_file(&info_path);
+            continue;
+        }
+
+        let written = write!(
+            info,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(original),
+            Local::now().format("%Y-%m-%dT%H:%M:%S")
+        );
+        if let Err(err) = written
+            .map_err(anyhow::Error::from)
+            .and_then(|_| fs::rename(path, &trashed).map_err(anyhow::Error::from))
+        {
+            let _ = fs::remove_file(&info_path);
+            return Err(err);
+        }
+        return Ok(trashed);
+    }
+    bail!("No free name in the trash for {:?}", path)
+}
+
+/// Move a trashed file back to its original path and drop its `.trashinfo`. A path that exists
+/// again is never overwritten.
+pub fn restore_from_trash(trashed: &Path, original: &Path) -> Result<()> {
+    if fs::symlink_metadata(original).is_ok() {
+        bail!("{:?} exists again", original);
+    }
+    let name = trashed
+        .file_name()
+        .ok_or_else(|| anyhow!("Not a trashed file: {:?}", trashed))?;
+    let info_path = trashed
+        .parent()
+        .and_then(Path::parent)
+        .ok_or_else(|| anyhow!("Not a trashed file: {:?}", trashed))?
+        .join("info")
+        .join(info_name(name));
+
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(trashed, original)
+        .with_context(|| format!("Failed to restore {:?} from the trash", original))?;
+    let _ = fs::remove_file(info_path);
+    Ok(())
+}
+
+/// Name of the `.trashinfo` file of a trashed file, kept byte for byte
+fn info_name(trashed_name: &OsStr) -> OsString {
+    let mut info_name = trashed_name.to_os_string();
+    info_name.push(".trashinfo");
+    info_name
+}
+
+/// Trash directories are only readable by their owner
+fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(dir)
+        .with_context(|| format!("Failed to create trash directory: {:?}", dir))
+}
+
+/// Percent-encode a path the way URIs are, keeping `/` as the separator. On unix the raw bytes
+/// are encoded, so a name that isn't valid UTF-8 is recorded as it is.
+fn encode_path(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec();
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().into_owned().into_bytes();
+
+    let mut encoded = String::new();
+    for byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_encode_path() {
+        assert_eq!(
+            encode_path(Path::new("/home/me/my file%.txt")),
+            "/home/me/my%20file%25.txt"
+        );
+        assert_eq!(encode_path(Path::new("caf\u{e9}")), "caf%C3%A9");
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let latin1 = Path::new(std::ffi::OsStr::from_bytes(b"caf\xe9"));
+            assert_eq!(encode_path(latin1), "caf%E9");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_name_is_trashed_as_it_is() -> Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = tempdir()?;
+        let trash = TrashDir {
+            path: temp_dir.path().join("Trash"),
+            top_dir: None,
+        };
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        let file = temp_dir.path().join(name);
+        fs::write(&file, "content")?;
+
+        let trashed = move_to_trash(&file, &trash)?;
+        assert_eq!(trashed, trash.path.join("files").join(name));
+        let info_path = trash
+            .path
+            .join("info")
+            .join(OsStr::from_bytes(b"caf\xe9.txt.trashinfo"));
+        assert!(fs::read_to_string(&info_path)?.contains("Path="));
+
+        restore_from_trash(&trashed, &file)?;
+        assert_eq!(fs::read_to_string(&file)?, "content");
+        assert!(!info_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_to_trash_records_the_original_path() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let trash = TrashDir {
+            path: temp_dir.path().join("Trash"),
+            top_dir: None,
+        };
+        let first = temp_dir.path().join("a").join("notes.txt");
+        let second = temp_dir.path().join("b").join("notes.txt");
+        for path in [&first, &second] {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, "content")?;
+        }
+
+        let trashed = move_to_trash(&first, &trash)?;
+        assert!(!first.exists());
+        assert_eq!(trashed, trash.path.join("files/notes.txt"));
+        let info = fs::read_to_string(trash.path.join("info/notes.txt.trashinfo"))?;
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains(&format!("Path={}\n", encode_path(&first))));
+        assert!(info.contains("DeletionDate="));
+
+        // a second file with the same name gets a new one
+        let trashed = move_to_trash(&second, &trash)?;
+        assert_eq!(trashed, trash.path.join("files/notes.txt.2"));
+        assert!(trash.path.join("info/notes.txt.2.trashinfo").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_per_mount_trash_records_relative_paths() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let trash = TrashDir {
+            path: temp_dir.path().join(".Trash-1000"),
+            top_dir: Some(temp_dir.path().to_path_buf()),
+        };
+        let file = temp_dir.path().join("data").join("old.log");
+        fs::create_dir_all(file.parent().unwrap())?;
+        fs::write(&file, "content")?;
+
+        move_to_trash(&file, &trash)?;
+        let info = fs::read_to_string(trash.path.join("info/old.log.trashinfo"))?;
+        assert!(info.contains("Path=data/old.log\n"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,551 @@
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Clear, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+use super::scanner::FileRecord;
+use super::utils::format_size;
+
+/// Order of the entries of a directory
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+    Size,
+    Age,
+    Type,
+    Name,
+}
+
+impl SortKey {
+    fn next(self) -> SortKey {
+        match self {
+            SortKey::Size => SortKey::Age,
+            SortKey::Age => SortKey::Type,
+            SortKey::Type => SortKey::Name,
+            SortKey::Name => SortKey::Size,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Size => "size",
+            SortKey::Age => "age",
+            SortKey::Type => "type",
+            SortKey::Name => "name",
+        }
+    }
+}
+
+/// A directory or a candidate file of the tree
+#[derive(Debug)]
+struct Node {
+    name: String,
+    depth: usize,
+    children: Vec<usize>,
+    /// The record of a file, `None` for a directory
+    record: Option<usize>,
+    /// Size of the candidates in the subtree, and how much of it is included
+    size: u64,
+    included_size: u64,
+    files: usize,
+    included_files: usize,
+    /// Newest modification time in the subtree, a directory is as young as its youngest file
+    modified: Option<SystemTime>,
+    expanded: bool,
+}
+
+/// What the browser was left with
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Confirmed,
+    Cancelled,
+}
+
+/// The candidate tree as it's browsed: which files are included, how entries are sorted and
+/// which directories are expanded. Kept apart from the terminal so it can be tested.
+struct Browser {
+    nodes: Vec<Node>,
+    included: Vec<bool>,
+    sort: SortKey,
+    cursor: usize,
+    confirming: bool,
+}
+
+impl Browser {
+    fn new(records: &[FileRecord], selected: &[bool], root: &str) -> Browser {
+        let mut nodes = vec![Node::directory(root.to_string(), 0)];
+        nodes[0].expanded = true;
+        let mut directories: HashMap<(usize, String), usize> = HashMap::new();
+        for (idx, record) in records.iter().enumerate() {
+            if !selected[idx] {
+                continue;
+            }
+            let mut parent = 0;
+            let components: Vec<String> = record
+                .relative_path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+            let Some((file_name, dirs)) = components.split_last() else {
+                continue;
+            };
+            for dir in dirs {
+                let depth = nodes[parent].depth + 1;
+                parent = *directories.entry((parent, dir.clone())).or_insert_with(|| {
+                    nodes.push(Node::directory(dir.clone(), depth));
+                    let child = nodes.len() - 1;
+                    nodes[parent].children.push(child);
+                    child
+                });
+            }
+            let depth = nodes[parent].depth + 1;
+            nodes.push(Node {
+                record: Some(idx),
+                size: record.metadata.len(),
+                files: 1,
+                modified: record.metadata.modified().ok(),
+                ..Node::directory(file_name.clone(), depth)
+            });
+            let child = nodes.len() - 1;
+            nodes[parent].children.push(child);
+        }
+
+        let mut browser = Browser {
+            nodes,
+            included: selected.to_vec(),
+            sort: SortKey::Size,
+            cursor: 0,
+            confirming: false,
+        };
+        browser.aggregate();
+        browser.sort_children();
+        browser
+    }
+
+    /// Recompute the sizes and counts of every directory, children always come after their
+    /// parent so going backwards sees them first
+    fn aggregate(&mut self) {
+        for idx in (0..self.nodes.len()).rev() {
+            if let Some(record) = self.nodes[idx].record {
+                let node = &mut self.nodes[idx];
+                let included = self.included[record];
+                node.included_files = included as usize;
+                node.included_size = if included { node.size } else { 0 };
+                continue;
+            }
+            let (mut size, mut included_size, mut files, mut included_files) = (0, 0, 0, 0);
+            let mut modified = None;
+            for &child in &self.nodes[idx].children {
+                let child = &self.nodes[child];
+                size += child.size;
+                included_size += child.included_size;
+                files += child.files;
+                included_files += child.included_files;
+                modified = modified.max(child.modified);
+            }
+            let node = &mut self.nodes[idx];
+            node.size = size;
+            node.included_size = included_size;
+            node.files = files;
+            node.included_files = included_files;
+            node.modified = modified;
+        }
+    }
+
+    fn sort_children(&mut self) {
+        let sort = self.sort;
+        for idx in 0..self.nodes.len() {
+            let mut children = std::mem::take(&mut self.nodes[idx].children);
+            let nodes = &self.nodes;
+            children.sort_by(|a, b| {
+                let (a, b) = (&nodes[*a], &nodes[*b]);
+                match sort {
+                    SortKey::Size => b.size.cmp(&a.size),
+                    SortKey::Age => a.modified.cmp(&b.modified),
+                    SortKey::Type => (a.record.is_some(), extension(&a.name))
+                        .cmp(&(b.record.is_some(), extension(&b.name))),
+                    SortKey::Name => std::cmp::Ordering::Equal,
+                }
+                .then_with(|| a.name.cmp(&b.name))
+            });
+            self.nodes[idx].children = children;
+        }
+    }
+
+    /// The rows on screen: the root and the entries of every expanded directory
+    fn visible(&self) -> Vec<usize> {
+        let mut rows = vec![];
+        let mut stack = vec![0];
+        while let Some(idx) = stack.pop() {
+            rows.push(idx);
+            let node = &self.nodes[idx];
+            if node.expanded {
+                stack.extend(node.children.iter().rev());
+            }
+        }
+        rows
+    }
+
+    fn subtree_records(&self, idx: usize, records: &mut Vec<usize>) {
+        let node = &self.nodes[idx];
+        records.extend(node.record);
+        for &child in &node.children {
+            self.subtree_records(child, records);
+        }
+    }
+
+    /// Include the entry under the cursor with its whole subtree, or exclude it once it's fully
+    /// included
+    fn toggle(&mut self) {
+        let idx = self.visible()[self.cursor];
+        let include = self.nodes[idx].included_files < self.nodes[idx].files;
+        let mut records = vec![];
+        self.subtree_records(idx, &mut records);
+        for record in records {
+            self.included[record] = include;
+        }
+        self.aggregate();
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        let idx = self.visible()[self.cursor];
+        if self.nodes[idx].record.is_none() {
+            self.nodes[idx].expanded = expanded;
+        }
+    }
+
+    /// Handle a key, returning the outcome once the browser is done
+    fn handle_key(&mut self, key: KeyCode) -> Option<Outcome> {
+        if self.confirming {
+            match key {
+                KeyCode::Char('y') | KeyCode::Enter => return Some(Outcome::Confirmed),
+                KeyCode::Char('n') | KeyCode::Esc => self.confirming = false,
+                _ => {}
+            }
+            return None;
+        }
+        let rows = self.visible().len();
+        match key {
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor = (self.cursor + 1).min(rows - 1),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(20),
+            KeyCode::PageDown => self.cursor = (self.cursor + 20).min(rows - 1),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = rows - 1,
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => self.set_expanded(true),
+            KeyCode::Left | KeyCode::Char('h') => self.set_expanded(false),
+            KeyCode::Char(' ') => self.toggle(),
+            KeyCode::Char('s') => {
+                self.sort = self.sort.next();
+                self.sort_children();
+            }
+            KeyCode::Char('c') => self.confirming = true,
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Outcome::Cancelled),
+            _ => {}
+        }
+        // collapsing may have hidden the rows below the cursor
+        self.cursor = self.cursor.min(self.visible().len() - 1);
+        None
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let root = &self.nodes[0];
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from("Select the files to clean".bold()),
+                Line::from(format!(
+                    "{} of {} files included, {} of {}, sorted by {}",
+                    root.included_files,
+                    root.files,
+                    format_size(root.included_size),
+                    format_size(root.size),
+                    self.sort.label()
+                )),
+            ]),
+            header,
+        );
+
+        let now = SystemTime::now();
+        let rows: Vec<Row> = self
+            .visible()
+            .into_iter()
+            .map(|idx| {
+                let node = &self.nodes[idx];
+                let check = match node.included_files {
+                    0 => "[ ]",
+                    n if n == node.files => "[x]",
+                    _ => "[~]",
+                };
+                let marker = match (node.record, node.expanded) {
+                    (Some(_), _) => " ",
+                    (None, true) => "▾",
+                    (None, false) => "▸",
+                };
+                let name = format!(
+                    "{} {}{} {}{}",
+                    check,
+                    "  ".repeat(node.depth),
+                    marker,
+                    node.name,
+                    if node.record.is_none() { "/" } else { "" }
+                );
+                let row = Row::new(vec![
+                    name,
+                    format_size(node.size),
+                    node.modified.map(|m| age(now, m)).unwrap_or_default(),
+                ]);
+                if node.included_files == 0 {
+                    row.dim()
+                } else {
+                    row
+                }
+            })
+            .collect();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Min(20),
+                Constraint::Length(12),
+                Constraint::Length(8),
+            ],
+        )
+        .header(Row::new(vec!["Path", "Size", "Age"]).bold())
+        .row_highlight_style(Style::new().reversed());
+        let mut state = TableState::default().with_selected(Some(self.cursor));
+        frame.render_stateful_widget(table, body, &mut state);
+
+        frame.render_widget(
+            Paragraph::new(
+                "↑↓ move  ←→ collapse/expand  space include/exclude  s sort  c confirm  q quit",
+            )
+            .dim(),
+            footer,
+        );
+
+        if self.confirming {
+            let area = centered(frame.area(), 60, 5);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(vec![
+                    Line::from(format!(
+                        "Clean {} files, {}?",
+                        root.included_files,
+                        format_size(root.included_size)
+                    )),
+                    Line::from(""),
+                    Line::from("y confirm  n go back".dim()),
+                ])
+                .block(Block::bordered().title("Confirm")),
+                area,
+            );
+        }
+    }
+}
+
+impl Node {
+    fn directory(name: String, depth: usize) -> Node {
+        Node {
+            name,
+            depth,
+            children: vec![],
+            record: None,
+            size: 0,
+            included_size: 0,
+            files: 0,
+            included_files: 0,
+            modified: None,
+            expanded: false,
+        }
+    }
+}
+
+fn extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn age(now: SystemTime, modified: SystemTime) -> String {
+    let days = now
+        .duration_since(modified)
+        .map(|d| d.as_secs() / 86400)
+        .unwrap_or(0);
+    format!("{}d", days)
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Browse the selected files full screen as a tree with the size of every directory, and
+/// choose which of them the action is applied to, unselecting the others. Quitting without
+/// confirming unselects them all.
+pub fn browse(records: &[FileRecord], selected: &mut [bool], root: &str) -> Result<()> {
+    if !selected.iter().any(|s| *s) {
+        return Ok(());
+    }
+    let mut browser = Browser::new(records, selected, root);
+    let mut terminal = ratatui::init();
+    let outcome = run(&mut terminal, &mut browser);
+    ratatui::restore();
+
+    let confirmed = outcome? == Outcome::Confirmed;
+    for (selected, included) in selected.iter_mut().zip(browser.included) {
+        *selected = confirmed && included;
+    }
+    Ok(())
+}
+
+fn run(terminal: &mut DefaultTerminal, browser: &mut Browser) -> Result<Outcome> {
+    loop {
+        terminal.draw(|frame| browser.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let Some(outcome) = browser.handle_key(key.code) {
+                return Ok(outcome);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn records(root: &Path, files: &[(&str, u64)]) -> Result<Vec<FileRecord>> {
+        files
+            .iter()
+            .map(|(name, size)| {
+                let path = root.join(name);
+                fs::create_dir_all(path.parent().unwrap())?;
+                fs::File::create(&path)?.set_len(*size)?;
+                Ok(FileRecord {
+                    relative_path: PathBuf::from(name),
+                    metadata: fs::metadata(&path)?,
+                    path,
+                    git_ignored: false,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tree_aggregates_and_toggles_subtrees() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let records = records(
+            temp_dir.path(),
+            &[
+                ("logs/a.log", 100),
+                ("logs/old/b.log", 300),
+                ("c.tmp", 50),
+                ("kept.txt", 10),
+            ],
+        )?;
+        let mut browser = Browser::new(&records, &[true, true, true, false], "root");
+        let names = |browser: &Browser| -> Vec<String> {
+            browser
+                .visible()
+                .iter()
+                .map(|idx| browser.nodes[*idx].name.clone())
+                .collect()
+        };
+        assert_eq!(names(&browser), vec!["root", "logs", "c.tmp"]);
+        assert_eq!(browser.nodes[0].size, 450);
+
+        // expand logs/, then exclude it with its whole subtree
+        browser.handle_key(KeyCode::Down);
+        browser.handle_key(KeyCode::Right);
+        assert_eq!(
+            names(&browser),
+            vec!["root", "logs", "old", "a.log", "c.tmp"]
+        );
+        browser.handle_key(KeyCode::Char(' '));
+        assert_eq!(browser.nodes[0].included_size, 50);
+        assert_eq!(browser.included, vec![false, false, true, false]);
+
+        browser.handle_key(KeyCode::Char('s'));
+        browser.handle_key(KeyCode::Char('s'));
+        browser.handle_key(KeyCode::Char('s'));
+        assert_eq!(
+            names(&browser),
+            vec!["root", "c.tmp", "logs", "a.log", "old"]
+        );
+
+        // collapsing keeps the cursor on screen
+        browser.handle_key(KeyCode::End);
+        browser.handle_key(KeyCode::Home);
+        browser.handle_key(KeyCode::Down);
+        browser.handle_key(KeyCode::Down);
+        browser.handle_key(KeyCode::Left);
+        assert_eq!(browser.cursor, 2);
+
+        assert_eq!(browser.handle_key(KeyCode::Char('c')), None);
+        assert_eq!(browser.handle_key(KeyCode::Char('n')), None);
+        assert_eq!(
+            browser.handle_key(KeyCode::Char('q')),
+            Some(Outcome::Cancelled)
+        );
+        browser.handle_key(KeyCode::Char('c'));
+        assert_eq!(
+            browser.handle_key(KeyCode::Char('y')),
+            Some(Outcome::Confirmed)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_draw() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let records = records(temp_dir.path(), &[("logs/a.log", 2048)])?;
+        let mut browser = Browser::new(&records, &[true], "root");
+        let mut terminal = Terminal::new(TestBackend::new(80, 12))?;
+        let mut screen = |browser: &Browser| -> Result<String> {
+            terminal.draw(|frame| browser.draw(frame))?;
+            Ok(terminal
+                .backend()
+                .buffer()
+                .content()
+                .iter()
+                .map(|cell| cell.symbol())
+                .collect())
+        };
+
+        let tree = screen(&browser)?;
+        assert!(tree.contains("1 of 1 files included"));
+        assert!(tree.contains("[x] ▾ root/"));
+        assert!(tree.contains("[x]   ▸ logs/"));
+
+        browser.handle_key(KeyCode::Char('c'));
+        assert!(screen(&browser)?.contains("Clean 1 files, 2.00 KiB?"));
+
+        Ok(())
+    }
+}
@@ -1,38 +1,175 @@
-use anyhow::{Context, Ok, Result};
+use anyhow::{Context, Result};
 use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
 use colored::*;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
-use std::collections::HashSet;
 use std::fs::{self};
-use std::io::BufRead;
-use std::path::PathBuf;
 
-pub fn delete_file(
-    path: &std::path::Path,
-    dry_run: bool,
-    files_to_ignore: &HashSet<PathBuf>,
-) -> Result<()> {
-    if let Some(file_name) = path.file_name() {
-        if files_to_ignore
-            .iter()
-            .any(|ignore_path| ignore_path.file_name() == Some(file_name))
-        {
-            println!("Skipping ignored file: {:?}", path);
-            return Ok(()); // Skip this file if its name matches any in the ignore set
-        }
-    }
+pub fn delete_file(path: &std::path::Path, dry_run: bool) -> Result<()> {
     if !dry_run {
         fs::remove_file(path).with_context(|| format!("Failed to delete file: {:?}", path))?;
     } else {
         if let Some(pth) = path.to_str() {
-            println!("\n {} could have been deleted", pth.bold().yellow());
+            eprintln!("\n {} could have been deleted", pth.bold().yellow());
         }
     }
 
     Ok(())
 }
 
+/// Absolute path of a file with only its directory resolved, so that a symlink stays the link
+/// itself instead of becoming its target
+pub fn absolute_path(path: &std::path::Path) -> Result<std::path::PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Not a file: {:?}", path))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => std::env::current_dir()?,
+    };
+    Ok(parent.join(name))
+}
+
+/// What a shred pass overwrites a file with
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShredPass {
+    Zeros,
+    Random,
+}
+
+/// How files are shredded: the passes overwriting them, in order, before they are unlinked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Shred {
+    pub passes: Vec<ShredPass>,
+}
+
+impl std::str::FromStr for Shred {
+    type Err = anyhow::Error;
+
+    /// Passes separated by commas such as `random,random,zeros`, or a number of random passes
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(count) = s.trim().parse::<usize>() {
+            if count == 0 {
+                anyhow::bail!("Expected at least one shred pass");
+            }
+            return Ok(Shred {
+                passes: vec![ShredPass::Random; count],
+            });
+        }
+        let passes = s
+            .split(',')
+            .map(|pass| match pass.trim() {
+                "zeros" | "zero" => Ok(ShredPass::Zeros),
+                "random" => Ok(ShredPass::Random),
+                other => Err(anyhow::anyhow!(
+                    "Invalid shred pass: {}, expected zeros or random",
+                    other
+                )),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Shred { passes })
+    }
+}
+
+/// Only a plain file with a single link can be shredded: overwriting a hardlinked file or a
+/// symlink's target would destroy data at other paths
+pub fn can_shred(path: &std::path::Path) -> bool {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return false;
+    };
+    if metadata.file_type().is_symlink() {
+        eprintln!("Skipping symlink, its target would be shredded: {:?}", path);
+        return false;
+    }
+    #[cfg(unix)]
+    if std::os::unix::fs::MetadataExt::nlink(&metadata) > 1 {
+        eprintln!("Skipping file with other hard links: {:?}", path);
+        return false;
+    }
+    true
+}
+
+/// Overwrite a file with every pass, syncing after each, then rename it to a random name and
+/// unlink it. This is no guarantee on copy-on-write filesystems and SSDs, which write the new
+/// content elsewhere and may keep the old blocks around.
+pub fn shred_file(path: &std::path::Path, shred: &Shred, dry_run: bool) -> Result<()> {
+    use std::io::{Seek, Write};
+
+    if dry_run {
+        if let Some(pth) = path.to_str() {
+            eprintln!("\n {} could have been shredded", pth.bold().yellow());
+        }
+        return Ok(());
+    }
+
+    let shred_err = || format!("Failed to shred file: {:?}", path);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(shred_err)?;
+    let len = file.metadata().with_context(shred_err)?.len();
+    let mut random = random_stream()?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    for pass in &shred.passes {
+        file.rewind().with_context(shred_err)?;
+        let mut left = len;
+        while left > 0 {
+            let chunk = left.min(buffer.len() as u64) as usize;
+            match pass {
+                ShredPass::Zeros => buffer[..chunk].fill(0),
+                ShredPass::Random => random.fill(&mut buffer[..chunk]),
+            }
+            file.write_all(&buffer[..chunk]).with_context(shred_err)?;
+            left -= chunk as u64;
+        }
+        file.sync_all().with_context(shred_err)?;
+    }
+    file.set_len(0).with_context(shred_err)?;
+    file.sync_all().with_context(shred_err)?;
+    drop(file);
+
+    // the name is overwritten too, as far as renaming does it
+    let mut name = [0u8; 8];
+    random.fill(&mut name);
+    let hidden = path.with_file_name(
+        name.iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>(),
+    );
+    fs::rename(path, &hidden).with_context(shred_err)?;
+    fs::remove_file(&hidden).with_context(shred_err)?;
+    Ok(())
+}
+
+/// A stream of unpredictable bytes keyed with a seed from the OS random number generator, a
+/// shred never falls back to a predictable stream
+fn random_stream() -> Result<blake3::OutputReader> {
+    let mut seed = [0u8; 32];
+    getrandom::fill(&mut seed)
+        .map_err(|err| anyhow::anyhow!("No random number generator to shred with: {}", err))?;
+    Ok(blake3::Hasher::new_keyed(&seed).finalize_xof())
+}
+
+/// Parse a cutoff date given as `YYYY-MM-DD` (midnight UTC), an RFC 3339 timestamp or an age
+/// relative to now such as `12h`, `30d`, `6w`, `3mo` or `1y`
 pub fn parse_cutoff_date(date_str: &str) -> anyhow::Result<DateTime<Utc>> {
+    parse_cutoff_date_from(date_str, Utc::now())
+}
+
+fn parse_cutoff_date_from(date_str: &str, now: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+    let date_str = date_str.trim();
+
+    if let core::result::Result::Ok(datetime) = DateTime::parse_from_rfc3339(date_str) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+
+    if let Some(age) = parse_relative_age(date_str) {
+        return now
+            .checked_sub_signed(age)
+            .with_context(|| format!("Age is too large: {}", date_str));
+    }
+
     let naive_date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
         .with_context(|| format!("Invalid date format: {}", date_str))?;
 
@@ -48,8 +185,95 @@ pub fn parse_cutoff_date(date_str: &str) -> anyhow::Result<DateTime<Utc>> {
     Ok(cutoff_date)
 }
 
+/// Parse ages such as `12h` or `30d`. Months are 30 days and years 365 days.
+fn parse_relative_age(age_str: &str) -> Option<chrono::Duration> {
+    let split = age_str.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = age_str.split_at(split);
+    let value: i64 = number.parse().ok()?;
+
+    match unit {
+        "h" => chrono::Duration::try_hours(value),
+        "d" => chrono::Duration::try_days(value),
+        "w" => chrono::Duration::try_weeks(value),
+        "mo" => chrono::Duration::try_days(value.checked_mul(30)?),
+        "y" => chrono::Duration::try_days(value.checked_mul(365)?),
+        _ => None,
+    }
+}
+
+/// Parse a size such as `500`, `500K`, `1.5GiB` or `10MB`. Single letter and IEC units (`K`,
+/// `KiB`) are powers of 1024, SI units (`KB`) are powers of 1000.
+pub fn parse_size(size_str: &str) -> anyhow::Result<u64> {
+    let trimmed = size_str.trim();
+    let split = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+
+    let value: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size: {}", size_str))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        _ => return Err(anyhow::anyhow!("Invalid size unit: {}", size_str)),
+    };
+
+    Ok((value * multiplier as f64).round() as u64)
+}
+
+/// Parse a size or a size range. `10M` is a minimum, `10M..2G` a range and either side of the
+/// range can be left out (`..2G`, `10M..`). Both ends are inclusive.
+pub fn parse_size_range(range_str: &str) -> anyhow::Result<(Option<u64>, Option<u64>)> {
+    let (min, max) = match range_str.split_once("..") {
+        Some((min, max)) => (min.trim(), Some(max.trim())),
+        None => (range_str.trim(), None),
+    };
+    let min = if min.is_empty() {
+        None
+    } else {
+        Some(parse_size(min)?)
+    };
+    let max = match max {
+        Some(max) if !max.is_empty() => Some(parse_size(max)?),
+        _ => None,
+    };
+
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(anyhow::anyhow!("Invalid size range: {}", range_str));
+        }
+    }
+
+    Ok((min, max))
+}
+
+/// Format a byte count with the largest IEC unit that keeps it above one, e.g. `1.50 GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
+
 pub fn collect_metrics(
-    report_data: &mut crate::ReportData,
+    report_data: &mut crate::report::Report,
     metadata: std::fs::Metadata,
     path: &std::path::Path,
     del_meta: (u32, u64),
@@ -75,140 +299,105 @@ pub fn collect_metrics(
     }
 }
 
-pub fn build_args(args: &mut crate::arg::Args, line_arg: &Vec<&str>) -> Result<()> {
-    if line_arg.len() <= 0 {
-        return Ok(());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size_units() -> Result<()> {
+        assert_eq!(parse_size("500")?, 500);
+        assert_eq!(parse_size("500B")?, 500);
+        assert_eq!(parse_size("500K")?, 500 * 1024);
+        assert_eq!(parse_size("1.5GiB")?, 3 * (1 << 29));
+        assert_eq!(parse_size("10MB")?, 10_000_000);
+        assert_eq!(parse_size("2 tb")?, 2_000_000_000_000);
+        assert!(parse_size("10 parsecs").is_err());
+        assert!(parse_size("K").is_err());
+        Ok(())
     }
 
-    let verb = line_arg[0];
-    match verb {
-        "--dir" | "-d" => {
-            // for duplicate directory in file
-            if args.dir.len() > 0 {
-                eprint!("Found duplicate verb, {}", verb);
-                return Ok(());
-            }
+    #[test]
+    fn test_parse_size_range() -> Result<()> {
+        assert_eq!(parse_size_range("10M")?, (Some(10 << 20), None));
+        assert_eq!(
+            parse_size_range("10M..2G")?,
+            (Some(10 << 20), Some(2 << 30))
+        );
+        assert_eq!(parse_size_range("..2G")?, (None, Some(2 << 30)));
+        assert_eq!(parse_size_range("10M..")?, (Some(10 << 20), None));
+        assert!(parse_size_range("2G..10M").is_err());
+        Ok(())
+    }
 
-            if line_arg.len() > 1 {
-                args.dir = line_arg[1].to_string();
-            } else {
-                eprint!(
-                    "Expected at least one arg for specified verb, {}, skipping...",
-                    verb
-                );
-            }
-        }
-        "--size" | "-s" => {
-            if args.min_size.is_some() {
-                eprint!("Found duplicate verb, {}", verb);
-                return Ok(());
-            }
+    #[test]
+    fn test_parse_cutoff_date_formats() -> Result<()> {
+        let now = Utc.with_ymd_and_hms(2024, 3, 31, 12, 0, 0).unwrap();
 
-            if line_arg.len() > 1 {
-                let val: u64 = line_arg[1].parse()?;
-                args.min_size = Some(val);
-            } else {
-                eprint!(
-                    "Expected at least one arg for specified verb, {}, skipping...",
-                    verb
-                );
-            }
-        }
-        "--dryrun" | "-n" => {
-            if args.dry_run {
-                eprint!("Found duplicate verb, {}", verb);
-                return Ok(());
-            }
-            args.dry_run = true;
-        }
-        "--dedup" | "-r" => {
-            if args.remove_duplicates {
-                eprint!("Found duplicate verb, {}", verb);
-                return Ok(());
-            }
-            args.remove_duplicates = true;
-        }
-        "--age" | "-a" => {
-            if args.age.is_some() {
-                eprint!("Found duplicate verb, {}", verb);
-                return Ok(());
-            }
-            if line_arg.len() > 1 {
-                args.age = Some(line_arg[1].to_string());
-            } else {
-                eprint!(
-                    "Expected at least one arg for specified verb, {}, skipping...",
-                    verb
-                );
-            }
-        }
-        "--files_to_ignore" | "-i" => {
-            if args.files_to_ignore.len() > 0 {
-                eprint!("Found duplicate verb, {}", verb);
-                return Ok(());
-            }
-
-            if line_arg.len() > 1 {
-                for val in line_arg[1..].iter() {
-                    args.files_to_ignore.push(val.to_string());
-                }
-            } else {
-                eprint!(
-                    "Expected at least one arg for specified verb, {}, skipping...",
-                    verb
-                );
-            }
-        }
-        "--types" | "-t" => {
-            if args.types.len() > 0 {
-                eprint!("Found duplicate verb, {}", verb);
-                return Ok(());
-            }
+        assert_eq!(
+            parse_cutoff_date_from("2024-01-15", now)?,
+            Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_cutoff_date_from("2024-01-15T10:30:00+02:00", now)?,
+            Utc.with_ymd_and_hms(2024, 1, 15, 8, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_cutoff_date_from("12h", now)?,
+            Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_cutoff_date_from("30d", now)?,
+            Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_cutoff_date_from("2w", now)?,
+            Utc.with_ymd_and_hms(2024, 3, 17, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_cutoff_date_from("1y", now)?,
+            Utc.with_ymd_and_hms(2023, 4, 1, 12, 0, 0).unwrap()
+        );
+        assert!(parse_cutoff_date_from("30 days", now).is_err());
+        assert!(parse_cutoff_date_from("d", now).is_err());
 
-            if line_arg.len() > 1 {
-                for val in line_arg[1..].iter() {
-                    args.types.push(val.to_string());
-                }
-            } else {
-                eprint!(
-                    "Expected at least one arg for specified verb, {}, skipping...",
-                    verb
-                );
-            }
-        }
-        _ => {
-            eprint!("unknown verb, {}, skipping...", verb);
-        }
+        Ok(())
     }
 
-    Ok(())
-}
-
-pub fn read_file_and_rebuild_args(args: &mut crate::arg::Args) -> Result<()> {
-    // ensure we don't have any values lurking in args. Config files
-    // takes precedence
-    args.clear();
-    let config_file = args.config_file.clone();
-
-    if config_file.is_none() {
-        eprint!("Config file needed to process this funtion");
-        std::process::exit(1);
+    #[test]
+    fn test_shred_passes() -> Result<()> {
+        assert_eq!(
+            "random,zeros".parse::<Shred>()?.passes,
+            vec![ShredPass::Random, ShredPass::Zeros]
+        );
+        assert_eq!("3".parse::<Shred>()?.passes, vec![ShredPass::Random; 3]);
+        assert!("0".parse::<Shred>().is_err());
+        assert!("random,ones".parse::<Shred>().is_err());
+        Ok(())
     }
 
-    let file = config_file.as_ref().unwrap();
-    let f = std::fs::File::open(file)?;
+    #[test]
+    fn test_shred_file() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("customers.csv");
+        fs::write(&path, "secret")?;
+        let linked = temp_dir.path().join("linked.csv");
+        fs::hard_link(&path, &linked)?;
 
-    for line in std::io::BufReader::new(f).lines() {
-        if let core::result::Result::Ok(line_val) = line {
-            let line_arg: Vec<&str> = line_val.split(' ').collect();
-            build_args(args, &line_arg)?;
-        }
-    }
+        // overwriting a hardlinked file would destroy the other link's content too
+        assert!(!can_shred(&path));
+        fs::remove_file(&linked)?;
+        assert!(can_shred(&path));
 
-    if args.dir.len() == 0 {
-        eprint!("dir not present in the config file");
-        std::process::exit(1);
+        shred_file(&path, &"zeros,random".parse()?, false)?;
+        assert!(!path.exists());
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 0);
+        Ok(())
     }
 
-    Ok(())
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(500), "500 B");
+        assert_eq!(format_size(1536), "1.50 KiB");
+        assert_eq!(format_size(3 * (1 << 29)), "1.50 GiB");
+    }
 }
@@ -0,0 +1,426 @@
+//! Clean up directories by file type, size, age, duplicates and path patterns.
+//!
+//! The [`Cleaner`] walks a directory once and applies an [`Action`] to every file selected by
+//! its filters, returning a [`Report`] of what was done. The `features` module holds the
+//! building blocks: the filter expression language, the pattern matcher and the cleaners.
+pub mod cleaner;
+pub mod error;
+pub mod features;
+pub mod report;
+
+pub use cleaner::{Cleaner, CleanerBuilder};
+pub use error::{Error, Result};
+pub use features::action::Action;
+pub use report::{DuplicateGroup, Report};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::fs::File;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_directory_cleaner_should_delete_files_of_the_specified_file_type() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("test.txt");
+        let file = File::create(&file_path)?;
+        file.set_len(1000)?; // 1000 bytes
+
+        let mut report = Report::new();
+
+        let file_types = vec!["txt".to_string()];
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        features::cleaner_file_type::directory_cleaner_based_on_file_type(
+            &dir_str,
+            &file_types,
+            false,
+            &mut report,
+            &[],
+        )?;
+
+        assert!(!file_path.exists(), "File should have been deleted");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_cleaner_should_not_delete_files_of_the_specified_file_type_in_dry_run_mode(
+    ) -> Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path = temp_dir.path().join("test.txt");
+        let file = File::create(&file_path)?;
+        file.set_len(1000)?; // 1000 bytes
+
+        let mut report = Report::new();
+
+        let file_types = vec!["txt".to_string()];
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        features::cleaner_file_type::directory_cleaner_based_on_file_type(
+            &dir_str,
+            &file_types,
+            true,
+            &mut report,
+            &[],
+        )?;
+
+        assert!(file_path.exists(), "File shouldn't be deleted in dry run");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_cleaner_should_delete_files_greater_than_the_specified_min_size() -> Result<()>
+    {
+        let temp_dir = tempdir()?;
+        let file_path_1 = temp_dir.path().join("test1.txt");
+        let file_path_2 = temp_dir.path().join("test2.txt");
+        let file_1 = File::create(&file_path_1)?;
+        let file_2 = File::create(&file_path_2)?;
+        file_1.set_len(4000)?; // 4000 bytes
+        file_2.set_len(500)?; // 500 bytes
+
+        let mut report = Report::new();
+
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        features::cleaner_file_size::directory_cleaner_based_on_file_size(
+            &dir_str,
+            2000,
+            false,
+            &mut report,
+            &[],
+        )?;
+
+        assert!(
+            !file_path_1.exists(),
+            "File should be deleted as it's more than the minimum size"
+        );
+
+        assert!(
+            file_path_2.exists(),
+            "File should not be deleted as it's less than the minimum size"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_cleaner_should_not_delete_files_greater_than_the_specified_min_size_in_dry_run_mode(
+    ) -> Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path_1 = temp_dir.path().join("test1.txt");
+        let file_path_2 = temp_dir.path().join("test2.txt");
+        let file_1 = File::create(&file_path_1)?;
+        let file_2 = File::create(&file_path_2)?;
+        file_1.set_len(4000)?; // 4000 bytes
+        file_2.set_len(500)?; // 500 bytes
+
+        let mut report = Report::new();
+
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        features::cleaner_file_size::directory_cleaner_based_on_file_size(
+            &dir_str,
+            2000,
+            true,
+            &mut report,
+            &[],
+        )?;
+
+        assert!(
+            file_path_1.exists(),
+            "File should not be deleted in dry run mode"
+        );
+
+        assert!(
+            file_path_2.exists(),
+            "File should not be deleted in dry run mode"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_cleaner_should_delete_files_that_are_duplicates() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path_1 = temp_dir.path().join("test 1.txt");
+        let file_path_2 = temp_dir.path().join("test 2.txt");
+        let file_path_3 = temp_dir.path().join("test 3.txt");
+        let file_1 = File::create(&file_path_1)?;
+        let file_2 = File::create(&file_path_2)?;
+        let file_3 = File::create(&file_path_3)?;
+        file_1.set_len(4000)?; // 4000 bytes
+        file_2.set_len(4000)?; // 4000 bytes
+        file_3.set_len(5000)?; // 5000 bytes
+
+        let mut report = Report::new();
+
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        features::cleaner_file_duplicate::directory_cleaner_based_on_duplicate_files(
+            &dir_str,
+            false,
+            &Default::default(),
+            &mut report,
+            &[],
+        )?;
+
+        assert!(
+            !(file_path_1.exists() && file_path_2.exists()),
+            "One of test 1 or test 2 should be deleted"
+        );
+
+        assert!(file_path_3.exists(), "test 3 shouldn't be deleted");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_cleaner_should_not_delete_files_that_are_duplicates_in_dry_run() -> Result<()>
+    {
+        let temp_dir = tempdir()?;
+        let file_path_1 = temp_dir.path().join("test 1.txt");
+        let file_path_2 = temp_dir.path().join("test 2.txt");
+        let file_path_3 = temp_dir.path().join("test 3.txt");
+        let file_1 = File::create(&file_path_1)?;
+        let file_2 = File::create(&file_path_2)?;
+        let file_3 = File::create(&file_path_3)?;
+        file_1.set_len(4000)?; // 4000 bytes
+        file_2.set_len(4000)?; // 4000 bytes
+        file_3.set_len(5000)?; // 5000 bytes
+
+        let mut report = Report::new();
+
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        features::cleaner_file_duplicate::directory_cleaner_based_on_duplicate_files(
+            &dir_str,
+            true,
+            &Default::default(),
+            &mut report,
+            &[],
+        )?;
+
+        assert!(
+            file_path_1.exists() && file_path_2.exists() && file_path_3.exists(),
+            "All files should still exist"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_cleaner_should_delete_files_within_file_limit_and_are_duplicates(
+    ) -> Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path_1 = temp_dir.path().join("test 1.txt");
+        let file_path_2 = temp_dir.path().join("test 2.txt");
+        let file_path_3 = temp_dir.path().join("test 3.txt");
+        let file_1 = File::create(&file_path_1)?;
+        let file_2 = File::create(&file_path_2)?;
+        let file_3 = File::create(&file_path_3)?;
+        file_1.set_len(4000)?; // 4000 bytes
+        file_2.set_len(4000)?; // 4000 bytes
+        file_3.set_len(5000)?; // 5000 bytes
+
+        let mut report = Report::new();
+
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        features::cleaner_file_duplicate::directory_cleaner_based_on_duplicate_files(
+            &dir_str,
+            false,
+            &Default::default(),
+            &mut report,
+            &[],
+        )?;
+
+        assert!(
+            !(file_path_1.exists() && file_path_2.exists()),
+            "One of test 1 or test 2 should be deleted"
+        );
+
+        features::cleaner_file_size::directory_cleaner_based_on_file_size(
+            &dir_str,
+            4500,
+            false,
+            &mut report,
+            &[],
+        )?;
+
+        assert!(!file_path_3.exists(), "test 3 should now be deleted");
+
+        assert!(
+            file_path_1.exists() || file_path_2.exists(),
+            "One of test 1 or test 2 should still exist"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_cleaner_should_not_delete_files_within_file_limit_and_are_duplicates_in_dry_run(
+    ) -> Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path_1 = temp_dir.path().join("test 1.txt");
+        let file_path_2 = temp_dir.path().join("test 2.txt");
+        let file_path_3 = temp_dir.path().join("test 3.txt");
+        let file_1 = File::create(&file_path_1)?;
+        let file_2 = File::create(&file_path_2)?;
+        let file_3 = File::create(&file_path_3)?;
+        file_1.set_len(4000)?; // 4000 bytes
+        file_2.set_len(4000)?; // 4000 bytes
+        file_3.set_len(5000)?; // 5000 bytes
+
+        let mut report = Report::new();
+
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        features::cleaner_file_duplicate::directory_cleaner_based_on_duplicate_files(
+            &dir_str,
+            true,
+            &Default::default(),
+            &mut report,
+            &[],
+        )?;
+
+        assert!(
+            file_path_1.exists() && file_path_2.exists() && file_path_3.exists(),
+            "All files should still exist"
+        );
+
+        features::cleaner_file_size::directory_cleaner_based_on_file_size(
+            &dir_str,
+            4500,
+            true,
+            &mut report,
+            &[],
+        )?;
+
+        assert!(
+            file_path_1.exists() && file_path_2.exists() && file_path_3.exists(),
+            "All files should still exist"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_cleaner_should_show_correct_report_after_deleting_files_greater_than_the_specified_min_size(
+    ) -> Result<()> {
+        let temp_dir = tempdir()?;
+        let file_path_1 = temp_dir.path().join("test1.txt");
+        let file_path_2 = temp_dir.path().join("test2.txt");
+        let file_1 = File::create(&file_path_1)?;
+        let file_2 = File::create(&file_path_2)?;
+        file_1.set_len(4000)?; // 4000 bytes
+        file_2.set_len(500)?; // 500 bytes
+
+        let mut report = Report::new();
+
+        let dir_str = temp_dir.path().to_str().unwrap().to_string();
+        features::cleaner_file_size::directory_cleaner_based_on_file_size(
+            &dir_str,
+            2000,
+            false,
+            &mut report,
+            &[],
+        )?;
+
+        assert!(
+            !file_path_1.exists(),
+            "File should be deleted as it's more than the minimum size"
+        );
+
+        assert!(
+            file_path_2.exists(),
+            "File should not be deleted as it's less than the minimum size"
+        );
+
+        assert!(report.files_deleted == 1);
+        assert!(report.files_scanned == 2);
+        assert!(report.total_files_retained == 1);
+        assert!(report.total_file_size_retained == 500);
+        assert!(report.total_file_size_deleted == 4000);
+        assert!(report.paths_deleted.len() == 1);
+        assert!(report.paths_retained.len() == 1);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod age_tests {
+        use super::*;
+        use chrono::{DateTime, Utc};
+        use filetime::{set_file_times, FileTime};
+        use std::fs;
+        use std::path::Path;
+        use tempfile::tempdir;
+
+        fn set_file_modification_time(path: &Path, datetime: DateTime<Utc>) {
+            let timestamp = datetime.timestamp();
+            let file_time = FileTime::from_unix_time(timestamp, 0);
+            set_file_times(path, file_time, file_time).expect("Failed to set file times");
+        }
+
+        #[test]
+        fn test_files_older_than_cutoff_date() {
+            let dir = tempdir().unwrap();
+            let dir_path = dir.path().to_str().unwrap().to_string();
+
+            // Create test files
+            let file_path_older = dir.path().join("older_file.txt");
+            let file_path_newer = dir.path().join("newer_file.txt");
+
+            // Set cutoff date to 1 day ago
+            let cutoff_date = Utc::now() - chrono::Duration::days(1);
+            let cutoff_date_str = cutoff_date.format("%Y-%m-%d").to_string();
+
+            // Create files
+            fs::write(&file_path_older, "older file content").unwrap();
+            fs::write(&file_path_newer, "newer file content").unwrap();
+
+            // Set file modification times (older and newer than the cutoff date)
+            set_file_modification_time(&file_path_older, cutoff_date - chrono::Duration::days(2)); // Older than cutoff
+            set_file_modification_time(&file_path_newer, cutoff_date + chrono::Duration::days(2)); // Newer than cutoff
+
+            let mut report = Report::new();
+
+            // Run directory cleaner
+            let result = features::cleaner_last_modified_time::directory_cleaner_based_on_age(
+                &dir_path,
+                cutoff_date_str,
+                false, // not a dry run, actually delete files
+                &mut report,
+                &[],
+            );
+
+            assert!(result.is_ok());
+            assert!(!file_path_older.exists()); // file should be deleted
+            assert!(file_path_newer.exists()); // file should not be deleted
+        }
+
+        #[test]
+        fn test_dry_run_mode_files_older_than_cutoff_date() {
+            let dir = tempdir().unwrap();
+            let dir_path = dir.path().to_str().unwrap().to_string();
+
+            // Create a test file
+            let file_path = dir.path().join("test_file.txt");
+            fs::write(&file_path, "test content").unwrap();
+
+            // Set cutoff date to 1 day ago
+            let cutoff_date = Utc::now() - chrono::Duration::days(1);
+            let cutoff_date_str = cutoff_date.format("%Y-%m-%d").to_string();
+
+            // Set file modification time (older than cutoff)
+            set_file_modification_time(&file_path, cutoff_date - chrono::Duration::days(2));
+
+            let mut report = Report::new();
+
+            // Run directory cleaner in dry run mode
+            let result = features::cleaner_last_modified_time::directory_cleaner_based_on_age(
+                &dir_path,
+                cutoff_date_str,
+                true, // dry run mode
+                &mut report,
+                &[],
+            );
+
+            assert!(result.is_ok());
+            assert!(file_path.exists()); // file should not be deleted
+        }
+    }
+}
@@ -0,0 +1,597 @@
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use colored::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::features::action::Action;
+use crate::features::journal::JournalEntry;
+use crate::features::registry;
+use crate::features::scanner::FileRecord;
+use crate::features::utils::format_size;
+
+mod breakdown;
+mod html;
+
+pub use breakdown::{Breakdown, Breakdowns, BREAKDOWN_ROWS};
+
+/// Version of the JSON report, bumped whenever a field changes meaning or goes away
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// How the report is written
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReportFormat {
+    /// Colored text for a terminal
+    #[default]
+    Text,
+    /// JSON following [`REPORT_SCHEMA_VERSION`], for scripts
+    Json,
+    /// One row per walked file with its decision, for spreadsheets
+    Csv,
+    /// Summary table and the largest selected files, for pull request comments
+    Markdown,
+    /// A single page with charts and sortable tables, for attaching to tickets
+    Html,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            _ => Err(anyhow::anyhow!(
+                "Invalid report format: {}, expected text, json, csv, markdown or html",
+                s
+            )),
+        }
+    }
+}
+
+/// How many of the largest selected files the Markdown report lists
+const TOP_OFFENDERS: usize = 10;
+
+/// Quote a CSV field when it holds a separator, a quote or a line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escape what would break a Markdown table cell
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// What was decided for a walked file and why
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Decision {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// Whether the file was selected for the action, `error` tells when applying it failed
+    pub selected: bool,
+    /// The filters that selected the file or failed to, or what else kept it
+    pub reason: String,
+    /// The filter predicates that matched the file, empty when none did
+    pub filters: Vec<String>,
+    /// What was done to the file, such as `delete` or `hardlink`, `None` when it was left alone
+    pub action: Option<String>,
+    /// Why the action failed
+    pub error: Option<String>,
+}
+
+impl Decision {
+    /// A decision for the file, with no action taken yet
+    pub fn new(record: &FileRecord, selected: bool, reason: impl Into<String>) -> Decision {
+        Decision {
+            path: record.path.clone(),
+            size: record.metadata.len(),
+            modified: record.metadata.modified().ok().map(DateTime::<Utc>::from),
+            selected,
+            reason: reason.into(),
+            filters: vec![],
+            action: None,
+            error: None,
+        }
+    }
+}
+
+/// A set of identical files and what happened to each copy
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DuplicateGroup {
+    /// The copy that was kept
+    pub keeper: String,
+    /// Copies that were removed
+    pub removed: Vec<String>,
+    /// Copies replaced with a link to the keeper
+    pub linked: Vec<String>,
+}
+
+/// What a cleaning run did, or would have done in a dry run
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub files_scanned: u32,
+    pub files_deleted: u32,
+    pub total_time_sec: u64,
+    pub total_file_size_deleted: u64,
+    pub total_file_size_retained: u64,
+    pub total_files_retained: u32,
+    pub files_linked: u32,
+    pub total_file_size_linked: u64,
+    /// Space saved by compressing files, what they took minus what their compressed copies take
+    pub total_file_size_saved: u64,
+    pub paths_deleted: Vec<String>,
+    pub paths_retained: Vec<String>,
+    /// Files the action was applied to, or would have been in a dry run
+    pub targets: Vec<PathBuf>,
+    pub duplicate_groups: Vec<DuplicateGroup>,
+    /// Every path acted on and how, empty in a dry run
+    pub journal: Vec<JournalEntry>,
+    /// Id of the run's journal, when one was written
+    pub run_id: Option<String>,
+    /// What was done to the selected files, the counts named deleted cover all actions
+    pub action: Action,
+    pub dry_run: bool,
+    pub started_at: DateTime<Utc>,
+    /// What was decided for every walked file
+    pub decisions: Vec<Decision>,
+    /// The cleaned directory
+    pub root: PathBuf,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Report {
+            files_scanned: 0,
+            files_deleted: 0,
+            total_time_sec: 0,
+            total_file_size_deleted: 0,
+            total_file_size_retained: 0,
+            total_files_retained: 0,
+            files_linked: 0,
+            total_file_size_linked: 0,
+            total_file_size_saved: 0,
+            paths_deleted: vec![],
+            paths_retained: vec![],
+            targets: vec![],
+            duplicate_groups: vec![],
+            journal: vec![],
+            run_id: None,
+            action: Action::default(),
+            dry_run: false,
+            started_at: Utc::now(),
+            decisions: vec![],
+            root: PathBuf::new(),
+        }
+    }
+
+    /// The report in a format meant for files and other programs, `None` for text which is
+    /// printed with [`print_report`](Self::print_report)
+    pub fn render(&self, format: ReportFormat) -> Result<Option<String>> {
+        Ok(match format {
+            ReportFormat::Text => None,
+            ReportFormat::Json => Some(format!("{}\n", self.to_json()?)),
+            ReportFormat::Csv => Some(self.to_csv()),
+            ReportFormat::Markdown => Some(self.to_markdown()),
+            ReportFormat::Html => Some(self.to_html()),
+        })
+    }
+
+    /// One row per walked file: path, size, modification time, whether it was selected, why,
+    /// the action taken and the error if the action failed, then the extension, directory and
+    /// filters the breakdowns group it by
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "path,size,modified,selected,reason,action,error,extension,directory,filters\n",
+        );
+        for decision in &self.decisions {
+            let fields = [
+                decision.path.display().to_string(),
+                decision.size.to_string(),
+                decision
+                    .modified
+                    .map(|m| m.to_rfc3339_opts(SecondsFormat::Secs, true))
+                    .unwrap_or_default(),
+                decision.selected.to_string(),
+                decision.reason.clone(),
+                decision.action.clone().unwrap_or_default(),
+                decision.error.clone().unwrap_or_default(),
+                breakdown::extension(&decision.path),
+                breakdown::top_level_directory(&decision.path, &self.root),
+                decision.filters.join("; "),
+            ];
+            let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// A summary table and the largest files the action was applied to
+    pub fn to_markdown(&self) -> String {
+        let past = self.action.past_tense();
+        let mut md = String::from("## Cleaning Report\n\n");
+        if self.dry_run {
+            md.push_str("_Dry run, nothing was touched._\n\n");
+        }
+        let mut rows = vec![
+            ("Files Scanned".to_string(), self.files_scanned.to_string()),
+            (format!("Files {}", past), self.files_deleted.to_string()),
+            (
+                "Files Retained".to_string(),
+                self.total_files_retained.to_string(),
+            ),
+            (
+                format!("Total File Size {}", past),
+                format_size(self.total_file_size_deleted),
+            ),
+            (
+                "Total File Size Retained".to_string(),
+                format_size(self.total_file_size_retained),
+            ),
+        ];
+        if let Action::Compress(_) = self.action {
+            rows.push((
+                "Space Saved By Compressing".to_string(),
+                format_size(self.total_file_size_saved),
+            ));
+        }
+        if self.files_linked > 0 {
+            rows.push(("Files Linked".to_string(), self.files_linked.to_string()));
+            rows.push((
+                "Space Reclaimed By Linking".to_string(),
+                format_size(self.total_file_size_linked),
+            ));
+        }
+        let errors = self.decisions.iter().filter(|d| d.error.is_some()).count();
+        if errors > 0 {
+            rows.push(("Errors".to_string(), errors.to_string()));
+        }
+        rows.push((
+            "Total Time (seconds)".to_string(),
+            self.total_time_sec.to_string(),
+        ));
+        md.push_str("| | |\n|---|---:|\n");
+        for (name, value) in rows {
+            md.push_str(&format!("| {} | {} |\n", name, value));
+        }
+
+        for (title, rows) in self.breakdowns().sections() {
+            if rows.is_empty() {
+                continue;
+            }
+            md.push_str(&format!(
+                "\n### {}\n\n| {} | Files {} | Size {} | Files Retained | Size Retained |\n|---|---:|---:|---:|---:|\n",
+                title,
+                title.trim_start_matches("By "),
+                past,
+                past
+            ));
+            for row in rows {
+                md.push_str(&format!(
+                    "| {} | {} | {} | {} | {} |\n",
+                    markdown_cell(&row.key),
+                    row.files_selected,
+                    format_size(row.bytes_selected),
+                    row.files_retained,
+                    format_size(row.bytes_retained)
+                ));
+            }
+        }
+
+        let mut offenders: Vec<&Decision> = self
+            .decisions
+            .iter()
+            .filter(|d| d.selected && d.error.is_none())
+            .collect();
+        offenders.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        if !offenders.is_empty() {
+            md.push_str(&format!(
+                "\n### Largest Files {}\n\n| Path | Size | Modified | Reason |\n|---|---:|---|---|\n",
+                past
+            ));
+            for decision in offenders.into_iter().take(TOP_OFFENDERS) {
+                md.push_str(&format!(
+                    "| {} | {} | {} | {} |\n",
+                    markdown_cell(&decision.path.display().to_string()),
+                    format_size(decision.size),
+                    decision
+                        .modified
+                        .map(|m| m.format("%Y-%m-%d").to_string())
+                        .unwrap_or_default(),
+                    markdown_cell(&decision.reason)
+                ));
+            }
+        }
+        md
+    }
+
+    /// The report as JSON, see [`REPORT_SCHEMA_VERSION`]
+    pub fn to_json(&self) -> Result<String> {
+        #[derive(Serialize)]
+        struct Totals {
+            files_scanned: u32,
+            files_selected: u32,
+            files_retained: u32,
+            files_linked: u32,
+            bytes_selected: u64,
+            bytes_retained: u64,
+            bytes_linked: u64,
+            bytes_saved_by_compressing: u64,
+            errors: usize,
+        }
+        #[derive(Serialize)]
+        struct Timing {
+            started_at: DateTime<Utc>,
+            total_time_sec: u64,
+        }
+        #[derive(Serialize)]
+        struct JsonReport<'a> {
+            schema_version: u32,
+            action: &'a str,
+            dry_run: bool,
+            run_id: Option<&'a str>,
+            totals: Totals,
+            timing: Timing,
+            breakdowns: Breakdowns,
+            files: &'a [Decision],
+            duplicate_groups: &'a [DuplicateGroup],
+        }
+
+        let report = JsonReport {
+            schema_version: REPORT_SCHEMA_VERSION,
+            action: self.action.name(),
+            dry_run: self.dry_run,
+            run_id: self.run_id.as_deref(),
+            totals: Totals {
+                files_scanned: self.files_scanned,
+                files_selected: self.files_deleted,
+                files_retained: self.total_files_retained,
+                files_linked: self.files_linked,
+                bytes_selected: self.total_file_size_deleted,
+                bytes_retained: self.total_file_size_retained,
+                bytes_linked: self.total_file_size_linked,
+                bytes_saved_by_compressing: self.total_file_size_saved,
+                errors: self.decisions.iter().filter(|d| d.error.is_some()).count(),
+            },
+            timing: Timing {
+                started_at: self.started_at,
+                total_time_sec: self.total_time_sec,
+            },
+            breakdowns: self.breakdowns(),
+            files: &self.decisions,
+            duplicate_groups: &self.duplicate_groups,
+        };
+        Ok(serde_json::to_string_pretty(&report)?)
+    }
+
+    /// Print the report to stdout
+    pub fn print_report(&self) {
+        // Section headers with bold and different colors
+        println!("{}", "Cleaning Report".bold().underline().blue());
+        println!();
+
+        // Files scanned
+        println!(
+            "{}: {}",
+            "Files Scanned".bold().cyan(),
+            self.files_scanned.to_string().green()
+        );
+
+        // Files deleted
+        println!(
+            "{}: {}",
+            format!("Files {}", self.action.past_tense()).bold().cyan(),
+            self.files_deleted.to_string().red()
+        );
+
+        // Files retained
+        println!(
+            "{}: {}",
+            "Files Retained".bold().cyan(),
+            self.total_files_retained.to_string().yellow()
+        );
+
+        // Time taken in seconds
+        println!(
+            "{}: {}",
+            "Total Time (seconds)".bold().cyan(),
+            self.total_time_sec.to_string().magenta()
+        );
+
+        // File sizes deleted, compressed files are still around so what they saved counts
+        if let Action::Compress(_) = self.action {
+            println!(
+                "{}: {}",
+                "Space Saved By Compressing".bold().cyan(),
+                format_size(self.total_file_size_saved).green()
+            );
+        } else {
+            println!(
+                "{}: {}",
+                format!("Total File Size {}", self.action.past_tense())
+                    .bold()
+                    .cyan(),
+                format_size(self.total_file_size_deleted).red()
+            );
+        }
+
+        // File sizes retained
+        println!(
+            "{}: {}",
+            "Total File Size Retained".bold().cyan(),
+            format_size(self.total_file_size_retained).yellow()
+        );
+
+        // Space reclaimed by replacing duplicates with links (if any)
+        if self.files_linked > 0 {
+            println!(
+                "{}: {}",
+                "Files Linked".bold().cyan(),
+                self.files_linked.to_string().green()
+            );
+            println!(
+                "{}: {}",
+                "Space Reclaimed By Linking".bold().cyan(),
+                format_size(self.total_file_size_linked).green()
+            );
+        }
+
+        // Bytes and counts per extension, directory and filter (if any)
+        for (title, rows) in self.breakdowns().sections() {
+            if rows.is_empty() {
+                continue;
+            }
+            println!("\n{}", title.bold().blue());
+            let width = rows.iter().map(|row| row.key.len()).max().unwrap_or(0);
+            for row in rows {
+                println!(
+                    "{:<width$}  {} {}  {} retained",
+                    row.key,
+                    format!(
+                        "{} files, {}",
+                        row.files_selected,
+                        format_size(row.bytes_selected)
+                    )
+                    .red(),
+                    self.action.past_tense().to_lowercase(),
+                    format!(
+                        "{} files, {}",
+                        row.files_retained,
+                        format_size(row.bytes_retained)
+                    )
+                    .yellow(),
+                    width = width
+                );
+            }
+        }
+
+        // Paths of deleted files (if any)
+        if !self.paths_deleted.is_empty() {
+            println!(
+                "\n{}",
+                format!("Paths {}", self.action.past_tense()).bold().red()
+            );
+            // shredded files are marked, so the report can serve as a record of the overwrite
+            let marker = match &self.action {
+                Action::Shred(shred) => format!("  shredded, {} passes", shred.passes.len()),
+                _ => String::new(),
+            };
+            for path in &self.paths_deleted {
+                println!("{}{}", path.red(), marker.bold());
+            }
+        }
+
+        // Files the action failed for (if any)
+        let failed: Vec<&Decision> = self
+            .decisions
+            .iter()
+            .filter(|d| d.error.is_some())
+            .collect();
+        if !failed.is_empty() {
+            println!("\n{}", "Errors".bold().red());
+            for decision in failed {
+                println!(
+                    "{}: {}",
+                    decision.path.display(),
+                    decision.error.as_deref().unwrap_or_default()
+                );
+            }
+        }
+
+        // Sections of the filters (if any)
+        for filter in registry() {
+            filter.print_report(self);
+        }
+
+        // Paths of retained files (if any)
+        if !self.paths_retained.is_empty() {
+            println!("\n{}", "Paths Retained".bold().yellow());
+            let paths_retained_unique: HashSet<_> = self.paths_retained.iter().cloned().collect();
+
+            for path in paths_retained_unique {
+                println!("{}", path.yellow());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        let decision = |path: &str, size: u64, selected: bool, reason: &str| Decision {
+            path: PathBuf::from(path),
+            size,
+            modified: DateTime::parse_from_rfc3339("2024-05-01T10:00:00Z")
+                .ok()
+                .map(|m| m.with_timezone(&Utc)),
+            selected,
+            reason: reason.to_string(),
+            filters: selected
+                .then(|| "ext(log)".to_string())
+                .into_iter()
+                .collect(),
+            action: selected.then(|| "delete".to_string()),
+            error: None,
+        };
+        Report {
+            files_scanned: 3,
+            files_deleted: 2,
+            total_files_retained: 1,
+            total_file_size_deleted: 3000,
+            total_file_size_retained: 10,
+            decisions: vec![
+                decision("logs/a|b.log", 1000, true, "matched ext(log)"),
+                decision("logs/big.log", 2000, true, "matched ext(log)"),
+                decision("notes, draft.txt", 10, false, "didn't match ext(log)"),
+            ],
+            ..Report::new()
+        }
+    }
+
+    #[test]
+    fn test_csv_has_a_row_per_file() {
+        let csv = report().to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "path,size,modified,selected,reason,action,error,extension,directory,filters"
+        );
+        assert_eq!(
+            lines[1],
+            "logs/a|b.log,1000,2024-05-01T10:00:00Z,true,matched ext(log),delete,,.log,logs/,ext(log)"
+        );
+        assert_eq!(
+            lines[3],
+            "\"notes, draft.txt\",10,2024-05-01T10:00:00Z,false,didn't match ext(log),,,.txt,.,"
+        );
+        assert_eq!(csv_field("say \"hi\""), r#""say ""hi""""#);
+    }
+
+    #[test]
+    fn test_markdown_lists_largest_files_first() {
+        let md = report().to_markdown();
+        assert!(md.contains("| Files Deleted | 2 |"));
+        assert!(md.contains("| Total File Size Deleted | 2.93 KiB |"));
+        let big = md.find("| logs/big.log | 1.95 KiB | 2024-05-01 | matched ext(log) |");
+        let small = md.find("| logs/a\\|b.log | 1000 B |");
+        assert!(big.unwrap() < small.unwrap());
+        assert!(!md.contains("notes"));
+        assert!(md.contains(
+            "| Extension | Files Deleted | Size Deleted | Files Retained | Size Retained |"
+        ));
+        assert!(md.contains("| .log | 2 | 2.93 KiB | 0 | 0 B |"));
+        assert!(md.contains("| ext(log) | 2 | 2.93 KiB | 0 | 0 B |"));
+    }
+}
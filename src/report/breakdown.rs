@@ -0,0 +1,211 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Component, Path};
+
+use super::{Decision, Report};
+
+/// How many rows a breakdown keeps, the rest is summed up as "other"
+pub const BREAKDOWN_ROWS: usize = 10;
+
+/// Files and bytes acted on and retained for one extension, directory or filter
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Breakdown {
+    pub key: String,
+    /// Files the action was applied to, the counts named selected match the JSON totals
+    pub files_selected: u32,
+    pub bytes_selected: u64,
+    pub files_retained: u32,
+    pub bytes_retained: u64,
+}
+
+/// The decisions summed up by extension, by directory right under the root and by the filter
+/// predicates that matched
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Breakdowns {
+    pub by_extension: Vec<Breakdown>,
+    pub by_directory: Vec<Breakdown>,
+    /// A file matched by several predicates counts for each of them, files no predicate
+    /// matched don't count at all
+    pub by_filter: Vec<Breakdown>,
+}
+
+impl Breakdowns {
+    pub fn is_empty(&self) -> bool {
+        self.by_extension.is_empty() && self.by_directory.is_empty() && self.by_filter.is_empty()
+    }
+
+    /// Title and rows of each breakdown, in the order the reports show them
+    pub fn sections(&self) -> [(&'static str, &[Breakdown]); 3] {
+        [
+            ("By Extension", &self.by_extension),
+            ("By Directory", &self.by_directory),
+            ("By Filter", &self.by_filter),
+        ]
+    }
+}
+
+impl Report {
+    /// Sum up the decisions by extension, directory and filter, largest first by the bytes acted
+    /// on. Extensions and directories past [`BREAKDOWN_ROWS`] are summed up as "other".
+    pub fn breakdowns(&self) -> Breakdowns {
+        Breakdowns {
+            by_extension: truncate(group(&self.decisions, |d| vec![extension(&d.path)])),
+            by_directory: truncate(group(&self.decisions, |d| {
+                vec![top_level_directory(&d.path, &self.root)]
+            })),
+            by_filter: group(&self.decisions, |d| d.filters.clone()),
+        }
+    }
+}
+
+fn group(decisions: &[Decision], keys: impl Fn(&Decision) -> Vec<String>) -> Vec<Breakdown> {
+    let mut groups: HashMap<String, Breakdown> = HashMap::new();
+    for decision in decisions {
+        for key in keys(decision) {
+            let breakdown = groups.entry(key.clone()).or_insert_with(|| Breakdown {
+                key,
+                ..Default::default()
+            });
+            add(breakdown, decision);
+        }
+    }
+    let mut rows: Vec<Breakdown> = groups.into_values().collect();
+    rows.sort_by(|a, b| {
+        (b.bytes_selected, b.bytes_retained)
+            .cmp(&(a.bytes_selected, a.bytes_retained))
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    rows
+}
+
+fn add(breakdown: &mut Breakdown, decision: &Decision) {
+    if decision.selected && decision.error.is_none() {
+        breakdown.files_selected += 1;
+        breakdown.bytes_selected += decision.size;
+    } else {
+        breakdown.files_retained += 1;
+        breakdown.bytes_retained += decision.size;
+    }
+}
+
+fn truncate(mut rows: Vec<Breakdown>) -> Vec<Breakdown> {
+    if rows.len() > BREAKDOWN_ROWS {
+        let rest = rows.split_off(BREAKDOWN_ROWS - 1);
+        rows.push(rest.into_iter().fold(
+            Breakdown {
+                key: "other".to_string(),
+                ..Default::default()
+            },
+            |mut other, row| {
+                other.files_selected += row.files_selected;
+                other.bytes_selected += row.bytes_selected;
+                other.files_retained += row.files_retained;
+                other.bytes_retained += row.bytes_retained;
+                other
+            },
+        ));
+    }
+    rows
+}
+
+pub(super) fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+        .unwrap_or_else(|| "(none)".to_string())
+}
+
+/// The directory right under the root the file lies in, `.` for files of the root itself
+pub(super) fn top_level_directory(path: &Path, root: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let mut components = relative
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)));
+    match (components.next(), components.next()) {
+        (Some(dir), Some(_)) => format!("{}/", dir.as_os_str().to_string_lossy()),
+        _ => ".".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn decision(path: &str, size: u64, selected: bool, filters: &[&str]) -> Decision {
+        Decision {
+            path: PathBuf::from(path),
+            size,
+            modified: None,
+            selected,
+            reason: String::new(),
+            filters: filters.iter().map(|f| f.to_string()).collect(),
+            action: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_breakdowns_sum_selected_and_retained() {
+        let mut failed = decision("/data/logs/c.LOG", 50, true, &["ext(log)"]);
+        failed.error = Some("permission denied".to_string());
+        let report = Report {
+            root: PathBuf::from("/data"),
+            decisions: vec![
+                decision("/data/logs/a.log", 300, true, &["ext(log)", "older(30d)"]),
+                decision("/data/logs/b.log", 100, true, &["ext(log)"]),
+                failed,
+                decision("/data/tmp/x.tmp", 1000, false, &[]),
+                decision("/data/README", 5, false, &[]),
+            ],
+            ..Report::new()
+        };
+        let breakdowns = report.breakdowns();
+
+        let log = Breakdown {
+            key: ".log".to_string(),
+            files_selected: 2,
+            bytes_selected: 400,
+            files_retained: 1,
+            bytes_retained: 50,
+        };
+        assert_eq!(breakdowns.by_extension[0], log);
+        assert_eq!(breakdowns.by_extension[1].key, ".tmp");
+        assert_eq!(breakdowns.by_extension[2].key, "(none)");
+        let keys: Vec<&str> = breakdowns
+            .by_directory
+            .iter()
+            .map(|b| b.key.as_str())
+            .collect();
+        assert_eq!(keys, ["logs/", "tmp/", "."]);
+        assert_eq!(
+            breakdowns.by_filter,
+            vec![
+                Breakdown {
+                    key: "ext(log)".to_string(),
+                    ..log
+                },
+                Breakdown {
+                    key: "older(30d)".to_string(),
+                    files_selected: 1,
+                    bytes_selected: 300,
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_breakdowns_sum_up_what_does_not_fit() {
+        let report = Report {
+            decisions: (0..15)
+                .map(|idx| decision(&format!("file.ext{}", idx), 100 + idx, true, &[]))
+                .collect(),
+            ..Report::new()
+        };
+        let by_extension = report.breakdowns().by_extension;
+        assert_eq!(by_extension.len(), BREAKDOWN_ROWS);
+        assert_eq!(by_extension[0].key, ".ext14");
+        assert_eq!(by_extension[BREAKDOWN_ROWS - 1].key, "other");
+        assert_eq!(by_extension[BREAKDOWN_ROWS - 1].files_selected, 6);
+    }
+}
@@ -0,0 +1,293 @@
+use super::{Breakdown, Decision, Report};
+use crate::features::utils::format_size;
+
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 2rem; color: #222; }
+h1 { margin-bottom: 0.2rem; }
+.note { color: #666; margin-top: 0; }
+.totals { display: flex; flex-wrap: wrap; gap: 1rem; margin: 1.5rem 0; }
+.total { border: 1px solid #ddd; border-radius: 6px; padding: 0.8rem 1.2rem; min-width: 10rem; }
+.total .value { font-size: 1.4rem; font-weight: 600; }
+.total .name { color: #666; font-size: 0.9rem; }
+.charts { display: flex; flex-wrap: wrap; gap: 2rem; }
+.chart rect { fill: #d9534f; }
+.chart text { font-size: 12px; fill: #222; }
+table { border-collapse: collapse; margin-bottom: 2rem; }
+th, td { padding: 0.3rem 0.8rem; border-bottom: 1px solid #eee; text-align: left; }
+td.number { text-align: right; }
+table.sortable th { cursor: pointer; user-select: none; }
+table.sortable th:hover { background: #f4f4f4; }
+"#;
+
+const SCRIPT: &str = r#"
+document.querySelectorAll('table.sortable').forEach(table => {
+  table.querySelectorAll('th').forEach((th, col) => th.addEventListener('click', () => {
+    const asc = th.dataset.order !== 'asc';
+    th.dataset.order = asc ? 'asc' : 'desc';
+    const value = row => row.cells[col].dataset.value ?? row.cells[col].textContent;
+    const numeric = th.dataset.type === 'number';
+    const body = table.tBodies[0];
+    Array.from(body.rows)
+      .sort((a, b) => {
+        const order = numeric ? Number(value(a)) - Number(value(b)) : value(a).localeCompare(value(b));
+        return asc ? order : -order;
+      })
+      .forEach(row => body.appendChild(row));
+  }));
+});
+"#;
+
+impl Report {
+    /// A single HTML page with no external assets: the totals, charts and tables of the
+    /// breakdowns, and sortable tables of the acted on and retained paths
+    pub fn to_html(&self) -> String {
+        let past = self.action.past_tense();
+        let acted: Vec<&Decision> = self
+            .decisions
+            .iter()
+            .filter(|d| d.selected && d.error.is_none())
+            .collect();
+        let retained: Vec<&Decision> = self
+            .decisions
+            .iter()
+            .filter(|d| !d.selected || d.error.is_some())
+            .collect();
+
+        let mut html = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n");
+        html.push_str("<meta charset=\"utf-8\">\n<title>Cleaning Report</title>\n");
+        html.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", STYLE));
+        html.push_str("<h1>Cleaning Report</h1>\n");
+        html.push_str(&format!(
+            "<p class=\"note\">{}{}, started {}</p>\n",
+            escape(&self.root.display().to_string()),
+            if self.dry_run {
+                " &middot; dry run, nothing was touched"
+            } else {
+                ""
+            },
+            self.started_at.format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+
+        let mut totals = vec![
+            ("Files Scanned".to_string(), self.files_scanned.to_string()),
+            (format!("Files {}", past), self.files_deleted.to_string()),
+            (
+                format!("Total File Size {}", past),
+                format_size(self.total_file_size_deleted),
+            ),
+            (
+                "Files Retained".to_string(),
+                self.total_files_retained.to_string(),
+            ),
+            (
+                "Total File Size Retained".to_string(),
+                format_size(self.total_file_size_retained),
+            ),
+        ];
+        if self.total_file_size_saved > 0 {
+            totals.push((
+                "Space Saved By Compressing".to_string(),
+                format_size(self.total_file_size_saved),
+            ));
+        }
+        if self.files_linked > 0 {
+            totals.push((
+                "Space Reclaimed By Linking".to_string(),
+                format_size(self.total_file_size_linked),
+            ));
+        }
+        let errors = self.decisions.iter().filter(|d| d.error.is_some()).count();
+        if errors > 0 {
+            totals.push(("Errors".to_string(), errors.to_string()));
+        }
+        html.push_str("<div class=\"totals\">\n");
+        for (name, value) in totals {
+            html.push_str(&format!(
+                "<div class=\"total\"><div class=\"value\">{}</div><div class=\"name\">{}</div></div>\n",
+                value, name
+            ));
+        }
+        html.push_str("</div>\n");
+
+        let breakdowns = self.breakdowns();
+        if !acted.is_empty() {
+            html.push_str("<div class=\"charts\">\n");
+            for (title, rows) in breakdowns.sections() {
+                let rows: Vec<&Breakdown> = rows.iter().filter(|r| r.files_selected > 0).collect();
+                if rows.is_empty() {
+                    continue;
+                }
+                let title = format!("Space {} {}", past, title);
+                html.push_str(&format!("<section>\n<h2>{}</h2>\n", title));
+                html.push_str(&bar_chart(&title, &rows));
+                html.push_str("</section>\n");
+            }
+            html.push_str("</div>\n");
+        }
+        for (title, rows) in breakdowns.sections() {
+            if rows.is_empty() {
+                continue;
+            }
+            html.push_str(&format!("<h2>{}</h2>\n", title));
+            html.push_str(&breakdown_table(
+                title.trim_start_matches("By "),
+                past,
+                rows,
+            ));
+        }
+
+        for (title, decisions) in [
+            (format!("Paths {}", past), acted),
+            ("Paths Retained".to_string(), retained),
+        ] {
+            if decisions.is_empty() {
+                continue;
+            }
+            html.push_str(&format!("<h2>{}</h2>\n", title));
+            html.push_str(&paths_table(&decisions));
+        }
+
+        html.push_str(&format!("<script>{}</script>\n</body>\n</html>\n", SCRIPT));
+        html
+    }
+}
+
+/// Horizontal bars of the bytes acted on
+fn bar_chart(title: &str, rows: &[&Breakdown]) -> String {
+    const BAR_WIDTH: f64 = 360.0;
+    let max = rows
+        .iter()
+        .map(|r| r.bytes_selected)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let height = rows.len() * 26 + 10;
+    let mut svg = format!(
+        "<svg class=\"chart\" xmlns=\"http://www.w3.org/2000/svg\" width=\"640\" height=\"{}\" role=\"img\" aria-label=\"{}\">\n",
+        height,
+        escape(title)
+    );
+    for (idx, row) in rows.iter().enumerate() {
+        let y = 5 + idx * 26;
+        let width = (row.bytes_selected as f64 / max as f64 * BAR_WIDTH).max(1.0);
+        svg.push_str(&format!(
+            "<text x=\"150\" y=\"{}\" text-anchor=\"end\">{}</text>\
+             <rect x=\"160\" y=\"{}\" width=\"{:.1}\" height=\"20\" rx=\"3\"><title>{} files</title></rect>\
+             <text x=\"{:.1}\" y=\"{}\">{}</text>\n",
+            y + 15,
+            escape(&row.key),
+            y,
+            width,
+            row.files_selected,
+            166.0 + width,
+            y + 15,
+            format_size(row.bytes_selected)
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn breakdown_table(key: &str, past: &str, rows: &[Breakdown]) -> String {
+    let mut table = format!(
+        "<table class=\"sortable\">\n<thead><tr><th>{}</th><th data-type=\"number\">Files {}</th>\
+         <th data-type=\"number\">Size {}</th><th data-type=\"number\">Files Retained</th>\
+         <th data-type=\"number\">Size Retained</th></tr></thead>\n<tbody>\n",
+        key, past, past
+    );
+    for row in rows {
+        table.push_str(&format!(
+            "<tr><td>{}</td><td class=\"number\">{}</td><td class=\"number\" data-value=\"{}\">{}</td>\
+             <td class=\"number\">{}</td><td class=\"number\" data-value=\"{}\">{}</td></tr>\n",
+            escape(&row.key),
+            row.files_selected,
+            row.bytes_selected,
+            format_size(row.bytes_selected),
+            row.files_retained,
+            row.bytes_retained,
+            format_size(row.bytes_retained)
+        ));
+    }
+    table.push_str("</tbody>\n</table>\n");
+    table
+}
+
+fn paths_table(decisions: &[&Decision]) -> String {
+    let mut table = String::from(
+        "<table class=\"sortable\">\n<thead><tr><th>Path</th><th data-type=\"number\">Size</th>\
+         <th data-type=\"number\">Modified</th><th>Reason</th></tr></thead>\n<tbody>\n",
+    );
+    for decision in decisions {
+        let reason = match &decision.error {
+            Some(error) => format!("{}, failed: {}", decision.reason, error),
+            None => decision.reason.clone(),
+        };
+        table.push_str(&format!(
+            "<tr><td>{}</td><td class=\"number\" data-value=\"{}\">{}</td><td data-value=\"{}\">{}</td><td>{}</td></tr>\n",
+            escape(&decision.path.display().to_string()),
+            decision.size,
+            format_size(decision.size),
+            decision.modified.map(|m| m.timestamp()).unwrap_or_default(),
+            decision
+                .modified
+                .map(|m| m.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default(),
+            escape(&reason)
+        ));
+    }
+    table.push_str("</tbody>\n</table>\n");
+    table
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_html_is_self_contained() {
+        let decision = |path: &str, size: u64, selected: bool| Decision {
+            path: PathBuf::from(path),
+            size,
+            modified: None,
+            selected,
+            reason: "matched ext(log)".to_string(),
+            filters: selected
+                .then(|| "ext(log)".to_string())
+                .into_iter()
+                .collect(),
+            action: None,
+            error: None,
+        };
+        let report = Report {
+            root: PathBuf::from("/data"),
+            files_deleted: 3,
+            decisions: vec![
+                decision("/data/logs/a.log", 3000, true),
+                decision("/data/logs/b.txt", 1000, true),
+                decision("/data/<top>.log", 500, true),
+                decision("/data/keep.txt", 10, false),
+            ],
+            ..Report::new()
+        };
+        let html = report.to_html();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(!html.contains("src=") && !html.contains("href="));
+        assert_eq!(html.matches("<svg").count(), 3);
+        assert!(html.contains(">.log</text>"));
+        assert!(html.contains(">logs/</text>"));
+        assert!(html.contains("<h2>By Filter</h2>"));
+        assert!(html.contains("<tr><td>.txt</td><td class=\"number\">1</td>"));
+        assert!(html.contains("/data/&lt;top&gt;.log"));
+        assert!(html.contains("<h2>Paths Deleted</h2>"));
+        assert!(html.contains("<h2>Paths Retained</h2>"));
+    }
+}